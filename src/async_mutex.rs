@@ -0,0 +1,260 @@
+//! A mutex for async tasks, whose `lock` is a future.
+//!
+//! Tasks that find the mutex locked queue their wakers in arrival order under
+//! a [`SpinMutex`]. Unlocking hands the mutex straight to the first of them,
+//! so a task arriving later cannot barge ahead, and the lock is only free when
+//! nobody is queued.
+//!
+//! Dropping a pending `lock` future leaves the queue, and passes the mutex on
+//! if it was just handed over.
+
+use alloc::vec::Vec;
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    future::Future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, Waker},
+};
+
+use crate::spin::SpinMutex;
+
+struct Waiter {
+    id: usize,
+    waker: Waker,
+}
+
+struct WaitList {
+    waiters: Vec<Waiter>,   // In arrival order.
+    handoff: Option<usize>, // Waiter the mutex was handed to, until it wakes up.
+    next_id: usize,
+}
+
+pub struct AsyncMutex<T: ?Sized> {
+    locked: AtomicBool,
+    wait_list: SpinMutex<WaitList>,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of an async mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be handed to the first waiter, or unlocked.
+///
+pub struct AsyncMutexGuard<'a, T: ?Sized + 'a> {
+    mutex: &'a AsyncMutex<T>,
+    data: &'a mut T,
+}
+
+/// The future returned by [`AsyncMutex::lock`].
+pub struct AsyncMutexLockFuture<'a, T: ?Sized + 'a> {
+    mutex: &'a AsyncMutex<T>,
+    id: Option<usize>, // Our place in the queue, once we have one.
+}
+
+unsafe impl<T: ?Sized + Send> Sync for AsyncMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for AsyncMutex<T> {}
+
+impl<T> AsyncMutex<T> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        AsyncMutex {
+            locked: AtomicBool::new(false),
+            wait_list: SpinMutex::new(WaitList {
+                waiters: Vec::new(),
+                handoff: None,
+                next_id: 0,
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized> AsyncMutex<T> {
+    /// Returns a future that resolves to a guard once the mutex is ours.
+    #[inline(always)]
+    pub fn lock(&self) -> AsyncMutexLockFuture<T> {
+        AsyncMutexLockFuture {
+            mutex: self,
+            id: None,
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<AsyncMutexGuard<T>> {
+        // The mutex is only left unlocked with nobody queued, so this can't
+        // overtake a waiter.
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(self.guard())
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    fn guard(&self) -> AsyncMutexGuard<T> {
+        AsyncMutexGuard {
+            mutex: self,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Hands the mutex to the first waiter, or unlocks it if there is none.
+    fn unlock(&self) {
+        let mut wait_list = self.wait_list.lock();
+        if wait_list.waiters.is_empty() {
+            self.locked.store(false, Ordering::Release);
+            return;
+        }
+        let next = wait_list.waiters.remove(0);
+        wait_list.handoff = Some(next.id);
+        drop(wait_list);
+        next.waker.wake();
+    }
+}
+
+impl<'a, T: ?Sized> Future for AsyncMutexLockFuture<'a, T> {
+    type Output = AsyncMutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        if self.id.is_none() {
+            if let Some(guard) = mutex.try_lock() {
+                return Poll::Ready(guard);
+            }
+        }
+        let mut wait_list = mutex.wait_list.lock();
+        match self.id {
+            Some(id) if wait_list.handoff == Some(id) => {
+                wait_list.handoff = None;
+                self.id = None;
+                return Poll::Ready(mutex.guard());
+            }
+            Some(id) => {
+                // Still queued, keep our waker current.
+                if let Some(waiter) = wait_list.waiters.iter_mut().find(|w| w.id == id) {
+                    if !waiter.waker.will_wake(cx.waker()) {
+                        waiter.waker = cx.waker().clone();
+                    }
+                }
+            }
+            None => {
+                // The unlocker takes the wait list lock before letting go.
+                if mutex
+                    .locked
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return Poll::Ready(mutex.guard());
+                }
+                let id = wait_list.next_id;
+                wait_list.next_id = id.wrapping_add(1);
+                wait_list.waiters.push(Waiter {
+                    id,
+                    waker: cx.waker().clone(),
+                });
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AsyncMutexLockFuture<'a, T> {
+    /// Leaves the queue, passing the mutex on if it was handed to us.
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut wait_list = self.mutex.wait_list.lock();
+            if wait_list.handoff == Some(id) {
+                wait_list.handoff = None;
+                drop(wait_list);
+                self.mutex.unlock();
+            } else {
+                wait_list.waiters.retain(|waiter| waiter.id != id);
+            }
+        }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AsyncMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "AsyncMutex {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "AsyncMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default> Default for AsyncMutex<T> {
+    fn default() -> Self {
+        AsyncMutex::new(T::default())
+    }
+}
+
+impl<T> From<T> for AsyncMutex<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AsyncMutexGuard<'a, T> {
+    /// The dropping of the AsyncMutexGuard hands the lock on, or releases it.
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<'a, T: ?Sized> Deref for AsyncMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for AsyncMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for AsyncMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for AsyncMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
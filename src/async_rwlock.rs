@@ -0,0 +1,600 @@
+//! A reader-writer lock for async tasks, whose acquisitions are futures.
+//!
+//! Tasks that cannot take the lock queue their wakers in arrival order under a
+//! [`SpinMutex`]. Releasing the lock hands it to the waiters at the front of
+//! the queue, as many readers as are queued together or one writer, so a
+//! writer that was woken is never overtaken by readers that arrived after it.
+//! Nothing takes the lock past a nonempty queue.
+//!
+//! As with [`RwLock`](crate::rwlock::RwLock), an upgradeable reader shares the
+//! lock with readers but excludes writers and other upgradeable readers. While
+//! it waits to upgrade, no new readers get in.
+
+use alloc::vec::Vec;
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    future::Future,
+    mem,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::spin::SpinMutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Upgradeable,
+    Write,
+}
+
+struct Waiter {
+    id: usize,
+    access: Access,
+    waker: Waker,
+}
+
+enum Upgrade {
+    Idle,
+    Waiting(Waker), // The upgradeable reader waits for the readers to leave.
+    Granted,        // The upgradeable reader is now the writer.
+}
+
+struct State {
+    readers: usize,
+    upgradeable: bool,
+    writer: bool,
+    upgrade: Upgrade,
+    waiters: Vec<Waiter>, // In arrival order.
+    granted: Vec<usize>,  // Waiters handed the lock, until they wake up.
+    next_id: usize,
+}
+
+impl State {
+    fn can_take(&self, access: Access) -> bool {
+        match access {
+            Access::Read => !self.writer && matches!(self.upgrade, Upgrade::Idle),
+            Access::Upgradeable => !self.writer && !self.upgradeable,
+            Access::Write => !self.writer && !self.upgradeable && self.readers == 0,
+        }
+    }
+
+    fn take(&mut self, access: Access) {
+        match access {
+            Access::Read => self.readers += 1,
+            Access::Upgradeable => self.upgradeable = true,
+            Access::Write => self.writer = true,
+        }
+    }
+
+    fn release(&mut self, access: Access) {
+        match access {
+            Access::Read => self.readers -= 1,
+            Access::Upgradeable => self.upgradeable = false,
+            Access::Write => self.writer = false,
+        }
+    }
+
+    /// Hands the lock to a waiting upgrade, then to the front of the queue,
+    /// returning the wakers to call once the spin lock is released.
+    fn grant(&mut self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        if self.readers == 0 {
+            if let Upgrade::Waiting(waker) = mem::replace(&mut self.upgrade, Upgrade::Idle) {
+                self.upgradeable = false;
+                self.writer = true;
+                self.upgrade = Upgrade::Granted;
+                wakers.push(waker);
+            }
+        }
+        while !self.waiters.is_empty() && self.can_take(self.waiters[0].access) {
+            let waiter = self.waiters.remove(0);
+            self.take(waiter.access);
+            self.granted.push(waiter.id);
+            wakers.push(waiter.waker);
+        }
+        wakers
+    }
+}
+
+fn wake_all(wakers: Vec<Waker>) {
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+pub struct AsyncRwLock<T: ?Sized> {
+    state: SpinMutex<State>,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will decrement the read count,
+/// potentially releasing the lock.
+pub struct AsyncRwLockReadGuard<'a, T: 'a + ?Sized> {
+    inner: &'a AsyncRwLock<T>,
+    data: &'a T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct AsyncRwLockWriteGuard<'a, T: 'a + ?Sized> {
+    inner: &'a AsyncRwLock<T>,
+    data: &'a mut T,
+}
+
+/// A guard that provides immutable data access but can be upgraded to [`AsyncRwLockWriteGuard`].
+///
+/// No writers or other upgradeable guards can exist while this is in scope.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct AsyncRwLockUpgradableGuard<'a, T: 'a + ?Sized> {
+    inner: &'a AsyncRwLock<T>,
+    data: &'a T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for AsyncRwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for AsyncRwLock<T> {}
+
+impl<T> AsyncRwLock<T> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        AsyncRwLock {
+            state: SpinMutex::new(State {
+                readers: 0,
+                upgradeable: false,
+                writer: false,
+                upgrade: Upgrade::Idle,
+                waiters: Vec::new(),
+                granted: Vec::new(),
+                next_id: 0,
+            }),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized> AsyncRwLock<T> {
+    /// Returns a future that resolves to a read guard.
+    #[inline(always)]
+    pub fn read(&self) -> AsyncRwLockReadFuture<T> {
+        AsyncRwLockReadFuture(Acquire::new(self, Access::Read))
+    }
+
+    /// Returns a future that resolves to a write guard.
+    #[inline(always)]
+    pub fn write(&self) -> AsyncRwLockWriteFuture<T> {
+        AsyncRwLockWriteFuture(Acquire::new(self, Access::Write))
+    }
+
+    /// Returns a future that resolves to an upgradeable read guard.
+    #[inline(always)]
+    pub fn upgradeable_read(&self) -> AsyncRwLockUpgradableReadFuture<T> {
+        AsyncRwLockUpgradableReadFuture(Acquire::new(self, Access::Upgradeable))
+    }
+
+    #[inline]
+    pub fn try_read(&self) -> Option<AsyncRwLockReadGuard<T>> {
+        self.try_take(Access::Read).then(|| self.read_guard())
+    }
+
+    #[inline]
+    pub fn try_write(&self) -> Option<AsyncRwLockWriteGuard<T>> {
+        self.try_take(Access::Write).then(|| self.write_guard())
+    }
+
+    #[inline]
+    pub fn try_upgradeable_read(&self) -> Option<AsyncRwLockUpgradableGuard<T>> {
+        self.try_take(Access::Upgradeable)
+            .then(|| self.upgradable_guard())
+    }
+
+    /// Return the number of readers that currently hold the lock, including
+    /// an upgradeable reader.
+    pub fn reader_count(&self) -> usize {
+        let state = self.state.lock();
+        state.readers + state.upgradeable as usize
+    }
+
+    /// Return the number of writers that currently hold the lock, 0 or 1.
+    pub fn writer_count(&self) -> usize {
+        self.state.lock().writer as usize
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner lock.
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn try_take(&self, access: Access) -> bool {
+        let mut state = self.state.lock();
+        // Queued waiters come first.
+        if state.waiters.is_empty() && state.can_take(access) {
+            state.take(access);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn release(&self, access: Access) {
+        let mut state = self.state.lock();
+        state.release(access);
+        let wakers = state.grant();
+        drop(state);
+        wake_all(wakers);
+    }
+
+    /// Trades held access `from` for `to`, letting in the waiters that now can.
+    fn convert(&self, from: Access, to: Access) {
+        let mut state = self.state.lock();
+        state.release(from);
+        state.take(to);
+        let wakers = state.grant();
+        drop(state);
+        wake_all(wakers);
+    }
+
+    fn read_guard(&self) -> AsyncRwLockReadGuard<T> {
+        AsyncRwLockReadGuard {
+            inner: self,
+            data: unsafe { &*self.data.get() },
+        }
+    }
+
+    fn write_guard(&self) -> AsyncRwLockWriteGuard<T> {
+        AsyncRwLockWriteGuard {
+            inner: self,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    fn upgradable_guard(&self) -> AsyncRwLockUpgradableGuard<T> {
+        AsyncRwLockUpgradableGuard {
+            inner: self,
+            data: unsafe { &*self.data.get() },
+        }
+    }
+}
+
+/// Taking the lock for one kind of access, shared by the futures below.
+struct Acquire<'a, T: ?Sized + 'a> {
+    lock: &'a AsyncRwLock<T>,
+    access: Access,
+    id: Option<usize>, // Our place in the queue, once we have one.
+}
+
+impl<'a, T: ?Sized> Acquire<'a, T> {
+    fn new(lock: &'a AsyncRwLock<T>, access: Access) -> Self {
+        Acquire {
+            lock,
+            access,
+            id: None,
+        }
+    }
+
+    fn poll_acquire(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock.state.lock();
+        match self.id {
+            None => {
+                if state.waiters.is_empty() && state.can_take(self.access) {
+                    state.take(self.access);
+                    return Poll::Ready(());
+                }
+                let id = state.next_id;
+                state.next_id = id.wrapping_add(1);
+                state.waiters.push(Waiter {
+                    id,
+                    access: self.access,
+                    waker: cx.waker().clone(),
+                });
+                self.id = Some(id);
+            }
+            Some(id) => {
+                if let Some(index) = state.granted.iter().position(|granted| *granted == id) {
+                    state.granted.swap_remove(index);
+                    self.id = None;
+                    return Poll::Ready(());
+                }
+                // Still queued, keep our waker current.
+                if let Some(waiter) = state.waiters.iter_mut().find(|w| w.id == id) {
+                    if !waiter.waker.will_wake(cx.waker()) {
+                        waiter.waker = cx.waker().clone();
+                    }
+                }
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a, T: ?Sized> Drop for Acquire<'a, T> {
+    /// Leaves the queue, giving the lock back if it was handed to us.
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.lock.state.lock();
+            match state.granted.iter().position(|granted| *granted == id) {
+                Some(index) => {
+                    state.granted.swap_remove(index);
+                    state.release(self.access);
+                }
+                // Leaving may unblock the waiters behind us.
+                None => state.waiters.retain(|waiter| waiter.id != id),
+            }
+            let wakers = state.grant();
+            drop(state);
+            wake_all(wakers);
+        }
+    }
+}
+
+/// The future returned by [`AsyncRwLock::read`].
+pub struct AsyncRwLockReadFuture<'a, T: ?Sized + 'a>(Acquire<'a, T>);
+
+/// The future returned by [`AsyncRwLock::write`].
+pub struct AsyncRwLockWriteFuture<'a, T: ?Sized + 'a>(Acquire<'a, T>);
+
+/// The future returned by [`AsyncRwLock::upgradeable_read`].
+pub struct AsyncRwLockUpgradableReadFuture<'a, T: ?Sized + 'a>(Acquire<'a, T>);
+
+impl<'a, T: ?Sized> Future for AsyncRwLockReadFuture<'a, T> {
+    type Output = AsyncRwLockReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.0.lock;
+        self.0.poll_acquire(cx).map(|()| lock.read_guard())
+    }
+}
+
+impl<'a, T: ?Sized> Future for AsyncRwLockWriteFuture<'a, T> {
+    type Output = AsyncRwLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.0.lock;
+        self.0.poll_acquire(cx).map(|()| lock.write_guard())
+    }
+}
+
+impl<'a, T: ?Sized> Future for AsyncRwLockUpgradableReadFuture<'a, T> {
+    type Output = AsyncRwLockUpgradableGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let lock = self.0.lock;
+        self.0.poll_acquire(cx).map(|()| lock.upgradable_guard())
+    }
+}
+
+/// The future returned by [`AsyncRwLockUpgradableGuard::upgrade`].
+pub struct AsyncRwLockUpgradeFuture<'a, T: ?Sized + 'a> {
+    inner: &'a AsyncRwLock<T>,
+    holding: bool, // Whether we still own the upgradeable read or the write.
+}
+
+impl<'a, T: ?Sized> Future for AsyncRwLockUpgradeFuture<'a, T> {
+    type Output = AsyncRwLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.inner;
+        let mut guard = inner.state.lock();
+        let state = &mut *guard;
+        match &mut state.upgrade {
+            Upgrade::Granted => state.upgrade = Upgrade::Idle,
+            Upgrade::Waiting(waker) => {
+                if !waker.will_wake(cx.waker()) {
+                    *waker = cx.waker().clone();
+                }
+                return Poll::Pending;
+            }
+            Upgrade::Idle if state.readers == 0 => {
+                state.upgradeable = false;
+                state.writer = true;
+            }
+            Upgrade::Idle => {
+                state.upgrade = Upgrade::Waiting(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        self.holding = false;
+        Poll::Ready(inner.write_guard())
+    }
+}
+
+impl<'a, T: ?Sized> Drop for AsyncRwLockUpgradeFuture<'a, T> {
+    /// Gives back whatever the upgrade holds, the write if it got there.
+    fn drop(&mut self) {
+        if self.holding {
+            let mut state = self.inner.state.lock();
+            let access = match mem::replace(&mut state.upgrade, Upgrade::Idle) {
+                Upgrade::Granted => Access::Write,
+                _ => Access::Upgradeable,
+            };
+            state.release(access);
+            let wakers = state.grant();
+            drop(state);
+            wake_all(wakers);
+        }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for AsyncRwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "AsyncRwLock {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "AsyncRwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default> Default for AsyncRwLock<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T> From<T> for AsyncRwLock<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'rwlock, T: ?Sized> AsyncRwLockUpgradableGuard<'rwlock, T> {
+    /// Upgrades to a writable lock guard once the readers are gone. New readers
+    /// wait until then.
+    #[inline]
+    pub fn upgrade(self) -> AsyncRwLockUpgradeFuture<'rwlock, T> {
+        let inner = self.inner;
+        // The future owns the upgradeable read from now on.
+        mem::forget(self);
+        AsyncRwLockUpgradeFuture {
+            inner,
+            holding: true,
+        }
+    }
+
+    /// Tries to upgrade to a writable lock guard, if there are no readers.
+    #[inline]
+    pub fn try_upgrade(self) -> Result<AsyncRwLockWriteGuard<'rwlock, T>, Self> {
+        let inner = self.inner;
+        let mut state = inner.state.lock();
+        if state.readers != 0 {
+            drop(state);
+            return Err(self);
+        }
+        state.upgradeable = false;
+        state.writer = true;
+        drop(state);
+        mem::forget(self);
+        Ok(inner.write_guard())
+    }
+
+    /// Downgrades the upgradeable lock guard to a readable, shared lock guard. Cannot fail.
+    pub fn downgrade(self) -> AsyncRwLockReadGuard<'rwlock, T> {
+        let inner = self.inner;
+        mem::forget(self);
+        inner.convert(Access::Upgradeable, Access::Read);
+        inner.read_guard()
+    }
+}
+
+impl<'rwlock, T: ?Sized> AsyncRwLockWriteGuard<'rwlock, T> {
+    /// Downgrades the writable lock guard to a readable, shared lock guard. Cannot fail.
+    pub fn downgrade(self) -> AsyncRwLockReadGuard<'rwlock, T> {
+        let inner = self.inner;
+        mem::forget(self);
+        inner.convert(Access::Write, Access::Read);
+        inner.read_guard()
+    }
+
+    /// Downgrades the writable lock guard to an upgradable, shared lock guard. Cannot fail.
+    pub fn downgrade_to_upgradeable(self) -> AsyncRwLockUpgradableGuard<'rwlock, T> {
+        let inner = self.inner;
+        mem::forget(self);
+        inner.convert(Access::Write, Access::Upgradeable);
+        inner.upgradable_guard()
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for AsyncRwLockReadGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for AsyncRwLockReadGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for AsyncRwLockUpgradableGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for AsyncRwLockUpgradableGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for AsyncRwLockWriteGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for AsyncRwLockWriteGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for AsyncRwLockReadGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for AsyncRwLockUpgradableGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for AsyncRwLockWriteGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> DerefMut for AsyncRwLockWriteGuard<'rwlock, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for AsyncRwLockReadGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        self.inner.release(Access::Read);
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for AsyncRwLockUpgradableGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        self.inner.release(Access::Upgradeable);
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for AsyncRwLockWriteGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        self.inner.release(Access::Write);
+    }
+}
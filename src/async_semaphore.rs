@@ -0,0 +1,188 @@
+//! A counting semaphore for async tasks, whose `acquire` is a future.
+//!
+//! Waiting tasks are served in arrival order: released permits go to the
+//! first waiter once there are enough for it, and nobody takes permits past a
+//! task that is still waiting.
+//!
+//! Interrupt handlers may release permits; see [the crate docs](crate#signalling-from-interrupt-handlers).
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{spin::SpinMutex, waker_list::WakerList};
+
+struct State {
+    permits: usize,
+    waiters: WakerList<usize>, // Each waiting for that many permits.
+}
+
+impl State {
+    /// Hands permits to the waiters at the front, while there are enough.
+    fn grant(&mut self) {
+        while let Some(&n) = self.waiters.front() {
+            if self.permits < n {
+                break;
+            }
+            self.permits -= n;
+            self.waiters.wake_front();
+        }
+    }
+}
+
+pub struct AsyncSemaphore {
+    state: SpinMutex<State>,
+}
+
+/// An RAII implementation of permits taken from an [`AsyncSemaphore`].
+/// When this structure is dropped (falls out of scope),
+/// the permits are given back.
+///
+pub struct AsyncSemaphorePermit<'a> {
+    sem: &'a AsyncSemaphore,
+    permits: usize,
+}
+
+/// The future returned by [`AsyncSemaphore::acquire`].
+pub struct AsyncSemaphoreAcquire<'a> {
+    sem: &'a AsyncSemaphore,
+    permits: usize,
+    id: Option<usize>, // Our place in the queue, once we have one.
+}
+
+impl AsyncSemaphore {
+    /// Creates a semaphore with `permits` permits.
+    #[inline(always)]
+    pub const fn new(permits: usize) -> Self {
+        AsyncSemaphore {
+            state: SpinMutex::new(State {
+                permits,
+                waiters: WakerList::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of permits available right now.
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    /// Returns a future that takes `n` permits once that many are available.
+    #[inline(always)]
+    pub fn acquire(&self, n: usize) -> AsyncSemaphoreAcquire {
+        AsyncSemaphoreAcquire {
+            sem: self,
+            permits: n,
+            id: None,
+        }
+    }
+
+    /// Takes `n` permits if that many are available and nobody is waiting.
+    pub fn try_acquire(&self, n: usize) -> Option<AsyncSemaphorePermit> {
+        let mut state = self.state.lock();
+        if state.waiters.waiting() == 0 && state.permits >= n {
+            state.permits -= n;
+            Some(self.permit(n))
+        } else {
+            None
+        }
+    }
+
+    /// Adds `n` permits, waking the waiters they are enough for.
+    pub fn release(&self, n: usize) {
+        let mut state = self.state.lock();
+        state.permits += n;
+        state.grant();
+    }
+
+    fn permit(&self, permits: usize) -> AsyncSemaphorePermit {
+        AsyncSemaphorePermit { sem: self, permits }
+    }
+}
+
+impl<'a> Future for AsyncSemaphoreAcquire<'a> {
+    type Output = AsyncSemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (sem, n) = (self.sem, self.permits);
+        let mut state = sem.state.lock();
+        match self.id {
+            None if state.waiters.waiting() == 0 && state.permits >= n => {
+                state.permits -= n;
+                return Poll::Ready(sem.permit(n));
+            }
+            None => self.id = Some(state.waiters.push(n, cx.waker())),
+            // `grant` took our permits when it picked us.
+            Some(id) if state.waiters.take_picked(id) => {
+                self.id = None;
+                return Poll::Ready(sem.permit(n));
+            }
+            Some(id) => state.waiters.update(id, cx.waker()),
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a> Drop for AsyncSemaphoreAcquire<'a> {
+    /// Leaves the queue, giving back the permits if we were handed them.
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.sem.state.lock();
+            if state.waiters.remove(id).is_some() {
+                state.permits += self.permits;
+            }
+            // Leaving may unblock the waiters behind us.
+            state.grant();
+        }
+    }
+}
+
+impl<'a> AsyncSemaphorePermit<'a> {
+    /// Returns the number of permits held.
+    #[inline(always)]
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keeps the permits out of the semaphore for good.
+    #[inline(always)]
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl<'a> Drop for AsyncSemaphorePermit<'a> {
+    /// The dropping of the AsyncSemaphorePermit gives its permits back.
+    fn drop(&mut self) {
+        if self.permits != 0 {
+            self.sem.release(self.permits);
+        }
+    }
+}
+
+impl Default for AsyncSemaphore {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl fmt::Debug for AsyncSemaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock();
+        write!(
+            f,
+            "AsyncSemaphore {{ permits: {}, waiters: {} }}",
+            state.permits,
+            state.waiters.waiting()
+        )
+    }
+}
+
+impl<'a> fmt::Debug for AsyncSemaphorePermit<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AsyncSemaphorePermit {{ permits: {} }}", self.permits)
+    }
+}
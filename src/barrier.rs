@@ -0,0 +1,101 @@
+//! A rendezvous of a fixed number of cpus.
+//!
+//! The barrier is reusable thanks to sense reversal: every round flips the
+//! global sense, and the cpus of a round wait for the sense they read on
+//! arrival to flip, so a fast cpu entering the next round cannot be confused
+//! with the stragglers of the previous one.
+
+use core::{
+    fmt,
+    hint::spin_loop,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::interrupt::{intr_get, intr_off, intr_on, mycpu, pop_off, push_off};
+
+pub struct Barrier {
+    n: usize,
+    count: AtomicUsize, // Cpus that have arrived in this round.
+    sense: AtomicBool,  // Flipped by the last cpu of every round.
+}
+
+/// Returned by [`Barrier::wait`], telling one cpu per round that it is the leader.
+pub struct BarrierWaitResult(bool);
+
+impl Barrier {
+    /// Creates a barrier for `n` cpus.
+    #[inline(always)]
+    pub const fn new(n: usize) -> Self {
+        Barrier {
+            n,
+            count: AtomicUsize::new(0),
+            sense: AtomicBool::new(false),
+        }
+    }
+
+    /// Spins with interrupts off until `n` cpus have called `wait`.
+    ///
+    /// The last cpu to arrive is the leader.
+    pub fn wait(&self) -> BarrierWaitResult {
+        push_off();
+        let result = self.rendezvous();
+        pop_off();
+        result
+    }
+
+    /// Spins with interrupts on until `n` cpus have called a `wait` method, so
+    /// that pending interrupts such as IPIs can be serviced meanwhile.
+    ///
+    /// Interrupts are back to their previous state on return. Must not be
+    /// called while this cpu holds a lock, which `push_off` protects.
+    pub fn wait_interruptible(&self) -> BarrierWaitResult {
+        debug_assert_eq!(
+            mycpu().noff,
+            0,
+            "Barrier::wait_interruptible inside push_off"
+        );
+        let enabled = intr_get();
+        intr_on();
+        let result = self.rendezvous();
+        if !enabled {
+            intr_off();
+        }
+        result
+    }
+
+    fn rendezvous(&self) -> BarrierWaitResult {
+        // The sense cannot flip before we arrive, so read it first.
+        let sense = !self.sense.load(Ordering::Relaxed);
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 == self.n {
+            self.count.store(0, Ordering::Relaxed);
+            // Release the count reset and the round's writes to the others.
+            self.sense.store(sense, Ordering::Release);
+            BarrierWaitResult(true)
+        } else {
+            while self.sense.load(Ordering::Acquire) != sense {
+                spin_loop();
+            }
+            BarrierWaitResult(false)
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Barrier {{ n: {} }}", self.n)
+    }
+}
+
+impl BarrierWaitResult {
+    /// Returns `true` for exactly one cpu of every round.
+    #[inline(always)]
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl fmt::Debug for BarrierWaitResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BarrierWaitResult {{ is_leader: {} }}", self.0)
+    }
+}
@@ -0,0 +1,323 @@
+//! A "big reader" lock for read-mostly data.
+//!
+//! Every cpu has its own reader counter for each `BrLock`, kept in the shared
+//! per-cpu array of `interrupt.rs`, so a reader only writes to its own cpu's
+//! cache line. A writer raises the writer flag and then waits for the counters
+//! of all cpus to drain, which makes writes expensive.
+//!
+//! Reads nest on the same cpu, even while a writer is waiting.
+//!
+//! Each lock takes one of the `usize::BITS` reader slots on its first use and
+//! gives it back when dropped.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    mem,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::interrupt::{cpu_id, pop_off, push_off, shared_cpu, MAX_BR_LOCKS, MAX_CORE_NUM};
+
+// Bitmap of the reader slots in use.
+static BR_SLOTS: AtomicUsize = AtomicUsize::new(0);
+
+const UNASSIGNED: usize = usize::MAX;
+
+pub struct BrLock<T: ?Sized> {
+    slot: AtomicUsize,
+    writer: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will decrement this cpu's read count.
+pub struct BrLockReadGuard<'a, T: 'a + ?Sized> {
+    readers: &'a AtomicUsize,
+    data: &'a T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct BrLockWriteGuard<'a, T: 'a + ?Sized> {
+    writer: &'a AtomicBool,
+    data: &'a mut T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for BrLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for BrLock<T> {}
+
+impl<T> BrLock<T> {
+    #[inline]
+    pub const fn new(data: T) -> Self {
+        BrLock {
+            slot: AtomicUsize::new(UNASSIGNED),
+            writer: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this `BrLock`, returning the underlying data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        free_slot(self.slot.load(Ordering::Relaxed));
+        let data = unsafe { ptr::read(&self.data) };
+        mem::forget(self);
+        data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized> BrLock<T> {
+    /// Locks this lock with shared read access, spinning while a writer holds
+    /// or waits for the lock, unless this cpu is already reading.
+    #[inline]
+    pub fn read(&self) -> BrLockReadGuard<T> {
+        loop {
+            match self.try_read() {
+                Some(guard) => return guard,
+                None => {
+                    while self.writer.load(Ordering::Relaxed) {
+                        spin_loop();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Attempt to acquire this lock with shared read access.
+    ///
+    /// Returns `None` if a writer holds or waits for the lock, unless this cpu
+    /// is already reading.
+    #[inline]
+    pub fn try_read(&self) -> Option<BrLockReadGuard<T>> {
+        push_off();
+        let readers = &shared_cpu(cpu_id() as usize).br_readers[self.slot()];
+        // SeqCst orders our increment before the load of the writer flag,
+        // the writer does the opposite.
+        let nested = readers.fetch_add(1, Ordering::SeqCst) != 0;
+        // A writer that raised its flag is waiting for us already.
+        if nested || !self.writer.load(Ordering::SeqCst) {
+            Some(BrLockReadGuard {
+                readers,
+                data: unsafe { &*self.data.get() },
+            })
+        } else {
+            readers.fetch_sub(1, Ordering::Release);
+            pop_off();
+            None
+        }
+    }
+
+    /// Lock this lock with exclusive write access, spinning until every cpu
+    /// has stopped reading.
+    #[inline]
+    pub fn write(&self) -> BrLockWriteGuard<T> {
+        push_off();
+        while self
+            .writer
+            .compare_exchange_weak(false, true, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.writer.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+        let slot = self.slot();
+        for cpu in 0..MAX_CORE_NUM {
+            let readers = &shared_cpu(cpu).br_readers[slot];
+            while readers.load(Ordering::SeqCst) != 0 {
+                spin_loop();
+            }
+        }
+        BrLockWriteGuard {
+            writer: &self.writer,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Attempt to lock this lock with exclusive write access.
+    ///
+    /// Returns `None` if a writer holds the lock or any cpu is reading.
+    #[inline]
+    pub fn try_write(&self) -> Option<BrLockWriteGuard<T>> {
+        push_off();
+        if self
+            .writer
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            pop_off();
+            return None;
+        }
+        let slot = self.slot();
+        if (0..MAX_CORE_NUM).all(|cpu| shared_cpu(cpu).br_readers[slot].load(Ordering::SeqCst) == 0)
+        {
+            Some(BrLockWriteGuard {
+                writer: &self.writer,
+                data: unsafe { &mut *self.data.get() },
+            })
+        } else {
+            self.writer.store(false, Ordering::Release);
+            pop_off();
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the `BrLock` mutably, no actual locking needs to
+    /// take place -- the mutable borrow statically guarantees no locks exist.
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner lock.
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Returns the reader slot of this lock, taking one on first use.
+    #[inline(always)]
+    fn slot(&self) -> usize {
+        let slot = self.slot.load(Ordering::Acquire);
+        if slot != UNASSIGNED {
+            return slot;
+        }
+        let new = alloc_slot();
+        match self
+            .slot
+            .compare_exchange(UNASSIGNED, new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => new,
+            Err(slot) => {
+                // Another cpu got there first.
+                free_slot(new);
+                slot
+            }
+        }
+    }
+}
+
+fn alloc_slot() -> usize {
+    let mut used = BR_SLOTS.load(Ordering::Relaxed);
+    loop {
+        let slot = (!used).trailing_zeros() as usize;
+        assert!(slot < MAX_BR_LOCKS, "too many BrLocks");
+        match BR_SLOTS.compare_exchange_weak(
+            used,
+            used | 1 << slot,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return slot,
+            Err(x) => used = x,
+        }
+    }
+}
+
+fn free_slot(slot: usize) {
+    if slot != UNASSIGNED {
+        BR_SLOTS.fetch_and(!(1 << slot), Ordering::Release);
+    }
+}
+
+impl<T: ?Sized> Drop for BrLock<T> {
+    fn drop(&mut self) {
+        // No guard is alive, so every counter of our slot is back to zero.
+        free_slot(*self.slot.get_mut());
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for BrLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "BrLock {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "BrLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default> Default for BrLock<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T> From<T> for BrLock<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for BrLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for BrLockReadGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for BrLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for BrLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for BrLockReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> Deref for BrLockWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for BrLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> Drop for BrLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        debug_assert!(self.readers.load(Ordering::Relaxed) > 0);
+        self.readers.fetch_sub(1, Ordering::Release);
+        pop_off();
+    }
+}
+
+impl<'a, T: ?Sized> Drop for BrLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.writer.store(false, Ordering::Release);
+        pop_off();
+    }
+}
@@ -0,0 +1,230 @@
+//! Locks following the priority ceiling protocol.
+//!
+//! Every `CeilingMutex` has a ceiling: the highest priority level of the code
+//! that ever takes it, interrupt handlers included. Locking raises the cpu to
+//! the ceiling, through the hook given to [`register_priority_hook`], so
+//! nothing else that takes the lock can run on this cpu until it is unlocked.
+//! On a single cpu, a task therefore never finds a lock taken and locks cannot
+//! deadlock. Until a hook is registered, locking keeps interrupts off instead,
+//! like the other spin locks.
+//!
+//! Taking a lock from above its ceiling breaks the protocol and panics.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+pub use crate::interrupt::{current_priority, register_priority_hook};
+use crate::interrupt::{pop_off, pop_priority, priority_hook_registered, push_off, push_priority};
+
+pub struct CeilingMutex<T: ?Sized, const CEILING: usize> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of a ceiling mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be unlocked and the cpu priority level restored.
+///
+pub struct CeilingMutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a AtomicBool,
+    data: &'a mut T,
+    level: usize,  // Priority level before locking.
+    irq_off: bool, // Whether locking turned interrupts off, for lack of a hook.
+}
+
+unsafe impl<T: ?Sized + Send, const CEILING: usize> Sync for CeilingMutex<T, CEILING> {}
+unsafe impl<T: ?Sized + Send, const CEILING: usize> Send for CeilingMutex<T, CEILING> {}
+
+impl<T, const CEILING: usize> CeilingMutex<T, CEILING> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        CeilingMutex {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized, const CEILING: usize> CeilingMutex<T, CEILING> {
+    /// Returns the ceiling of this lock.
+    #[inline(always)]
+    pub const fn ceiling(&self) -> usize {
+        CEILING
+    }
+
+    /// Raises the cpu to the ceiling and locks, spinning while another cpu
+    /// holds the lock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cpu is above the ceiling.
+    #[inline(always)]
+    pub fn lock(&self) -> CeilingMutexGuard<T> {
+        let (level, irq_off) = self.raise();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Wait until the lock looks unlocked before retrying
+            while self.is_locked() {
+                spin_loop();
+            }
+        }
+        CeilingMutexGuard {
+            lock: &self.locked,
+            data: unsafe { &mut *self.data.get() },
+            level,
+            irq_off,
+        }
+    }
+
+    /// Like [`lock`](CeilingMutex::lock), but returns `None` if the lock is taken.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<CeilingMutexGuard<T>> {
+        let (level, irq_off) = self.raise();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(CeilingMutexGuard {
+                lock: &self.locked,
+                data: unsafe { &mut *self.data.get() },
+                level,
+                irq_off,
+            })
+        } else {
+            lower(level, irq_off);
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    fn raise(&self) -> (usize, bool) {
+        let level = current_priority();
+        assert!(
+            level <= CEILING,
+            "CeilingMutex locked at priority {} above its ceiling {}",
+            level,
+            CEILING
+        );
+        // Without a hook nothing keeps the handlers that take the lock from
+        // running under it.
+        let irq_off = !priority_hook_registered();
+        if irq_off {
+            push_off();
+        }
+        (push_priority(CEILING), irq_off)
+    }
+}
+
+/// Undoes [`CeilingMutex::raise`].
+fn lower(level: usize, irq_off: bool) {
+    pop_priority(level);
+    if irq_off {
+        pop_off();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, const CEILING: usize> fmt::Debug for CeilingMutex<T, CEILING> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Only peek at the lock word, locking could break the ceiling.
+        if self.is_locked() {
+            write!(f, "CeilingMutex {{ <locked> }}")
+        } else {
+            write!(f, "CeilingMutex {{ ceiling: {} }}", CEILING)
+        }
+    }
+}
+
+impl<T: ?Sized + Default, const CEILING: usize> Default for CeilingMutex<T, CEILING> {
+    fn default() -> Self {
+        CeilingMutex::new(T::default())
+    }
+}
+
+impl<T, const CEILING: usize> From<T> for CeilingMutex<T, CEILING> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized> Drop for CeilingMutexGuard<'a, T> {
+    /// The dropping of the CeilingMutexGuard unlocks, then lowers the cpu back.
+    fn drop(&mut self) {
+        self.lock.store(false, Ordering::Release);
+        lower(self.level, self.irq_off);
+    }
+}
+
+impl<'a, T: ?Sized> Deref for CeilingMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for CeilingMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for CeilingMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for CeilingMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupt::intr_get;
+
+    #[test]
+    fn no_hook_test() {
+        // No test of the crate itself registers a hook.
+        let x = CeilingMutex::<_, 1>::new(0);
+        let guard = x.lock();
+        assert!(!intr_get());
+        drop(guard);
+        assert!(intr_get());
+        assert!(x.try_lock().is_some());
+        assert!(intr_get());
+    }
+}
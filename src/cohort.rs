@@ -0,0 +1,272 @@
+//! A NUMA-aware cohort lock.
+//!
+//! The lock is made of a global ticket lock plus one local ticket lock per NUMA
+//! node. A cpu first takes the lock of its own node and then the global lock.
+//! On release, if another cpu of the same node is already waiting, the global
+//! lock is handed over to it together with the local lock, so the protected
+//! cache lines stay on one node. The number of such intra-node handoffs in a
+//! row is bounded so that other nodes cannot be starved.
+
+use core::{
+    cell::UnsafeCell,
+    default::Default,
+    fmt,
+    hint::spin_loop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupt::{pop_off, push_off},
+    topology::{current_node, MAX_NUMA_NODES},
+};
+
+/// The default bound of consecutive intra-node handoffs.
+pub const DEFAULT_MAX_HANDOFFS: usize = 64;
+
+#[repr(align(64))]
+struct GlobalLock {
+    next_ticket: AtomicUsize,
+    next_serving: AtomicUsize,
+}
+
+#[repr(align(64))]
+struct NodeLock {
+    next_ticket: AtomicUsize,
+    next_serving: AtomicUsize,
+    // The fields below are only accessed by the holder of this local lock.
+    global_owned: AtomicBool, // Was the global lock handed over with the local lock?
+    global_ticket: AtomicUsize, // Ticket this node holds on the global lock.
+    handoffs: AtomicUsize,    // Consecutive intra-node handoffs so far.
+}
+
+impl NodeLock {
+    const fn new() -> Self {
+        Self {
+            next_ticket: AtomicUsize::new(0),
+            next_serving: AtomicUsize::new(0),
+            global_owned: AtomicBool::new(false),
+            global_ticket: AtomicUsize::new(0),
+            handoffs: AtomicUsize::new(0),
+        }
+    }
+}
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_NODE_LOCK: NodeLock = NodeLock::new();
+
+pub struct CohortMutex<T: ?Sized> {
+    global: GlobalLock,
+    nodes: [NodeLock; MAX_NUMA_NODES],
+    max_handoffs: usize,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of a mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be unlocked.
+///
+pub struct CohortMutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a CohortMutex<T>,
+    node: usize,
+    ticket: usize,
+    data: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for CohortMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for CohortMutex<T> {}
+
+impl<T> CohortMutex<T> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        Self::with_max_handoffs(data, DEFAULT_MAX_HANDOFFS)
+    }
+
+    /// Creates a cohort lock that passes the global lock to at most
+    /// `max_handoffs` waiters of the same node in a row.
+    #[inline(always)]
+    pub const fn with_max_handoffs(data: T, max_handoffs: usize) -> Self {
+        CohortMutex {
+            global: GlobalLock {
+                next_ticket: AtomicUsize::new(0),
+                next_serving: AtomicUsize::new(0),
+            },
+            nodes: [DEFAULT_NODE_LOCK; MAX_NUMA_NODES],
+            max_handoffs,
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized> CohortMutex<T> {
+    #[inline(always)]
+    pub fn lock(&self) -> CohortMutexGuard<T> {
+        push_off();
+        let node = current_node();
+        let local = &self.nodes[node];
+        let ticket = local.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while local.next_serving.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+        if !local.global_owned.load(Ordering::Relaxed) {
+            let global_ticket = self.global.next_ticket.fetch_add(1, Ordering::Relaxed);
+            while self.global.next_serving.load(Ordering::Acquire) != global_ticket {
+                spin_loop();
+            }
+            local.global_ticket.store(global_ticket, Ordering::Relaxed);
+        }
+        CohortMutexGuard {
+            lock: self,
+            node,
+            ticket,
+            // Safety
+            // We hold both our node's lock and the global lock, the latter
+            // either taken by us or handed over by the previous local holder.
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<CohortMutexGuard<T>> {
+        push_off();
+        let node = current_node();
+        let local = &self.nodes[node];
+        let ticket = match try_ticket(&local.next_ticket, &local.next_serving) {
+            Some(ticket) => ticket,
+            None => {
+                pop_off();
+                return None;
+            }
+        };
+        if !local.global_owned.load(Ordering::Relaxed) {
+            match try_ticket(&self.global.next_ticket, &self.global.next_serving) {
+                Some(global_ticket) => local.global_ticket.store(global_ticket, Ordering::Relaxed),
+                None => {
+                    // Give the local lock back, nobody can have been handed anything.
+                    local.next_serving.store(ticket + 1, Ordering::Release);
+                    pop_off();
+                    return None;
+                }
+            }
+        }
+        Some(CohortMutexGuard {
+            lock: self,
+            node,
+            ticket,
+            data: unsafe { &mut *self.data.get() },
+        })
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        let ticket = self.global.next_ticket.load(Ordering::Relaxed);
+        self.global.next_serving.load(Ordering::Relaxed) != ticket
+    }
+}
+
+/// Takes a ticket only if it would be served right away.
+#[inline(always)]
+fn try_ticket(next_ticket: &AtomicUsize, next_serving: &AtomicUsize) -> Option<usize> {
+    next_ticket
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |ticket| {
+            if next_serving.load(Ordering::Acquire) == ticket {
+                Some(ticket + 1)
+            } else {
+                None
+            }
+        })
+        .ok()
+}
+
+impl<'a, T: ?Sized> Drop for CohortMutexGuard<'a, T> {
+    /// The dropping of the CohortMutexGuard will release the lock it was created from,
+    /// handing the global lock over to the next waiter of the same node if allowed.
+    fn drop(&mut self) {
+        let local = &self.lock.nodes[self.node];
+        let next = self.ticket + 1;
+        let waiting = local.next_ticket.load(Ordering::Relaxed) != next;
+        let handoffs = local.handoffs.load(Ordering::Relaxed);
+        if waiting && handoffs < self.lock.max_handoffs {
+            local.handoffs.store(handoffs + 1, Ordering::Relaxed);
+            local.global_owned.store(true, Ordering::Relaxed);
+        } else {
+            local.handoffs.store(0, Ordering::Relaxed);
+            local.global_owned.store(false, Ordering::Relaxed);
+            let global_ticket = local.global_ticket.load(Ordering::Relaxed);
+            self.lock
+                .global
+                .next_serving
+                .store(global_ticket + 1, Ordering::Release);
+        }
+        local.next_serving.store(next, Ordering::Release);
+        pop_off();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for CohortMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "CohortMutex {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "CohortMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default> Default for CohortMutex<T> {
+    fn default() -> Self {
+        CohortMutex::new(T::default())
+    }
+}
+
+impl<T> From<T> for CohortMutex<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for CohortMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for CohortMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for CohortMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for CohortMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
@@ -0,0 +1,124 @@
+//! Condition variables for the crate's locks.
+//!
+//! A waiter reads the condvar's sequence number while it still holds the
+//! lock, then unlocks and waits for the sequence to change. A notifier bumps
+//! the sequence, so a notification sent between the unlock and the wait is
+//! not lost.
+//!
+//! The lock's `push_off` is undone while waiting, so interrupts are back on
+//! unless the waiter holds another lock. `Condvar` spins; `Condvar<S>` with a
+//! [`Scheduler`] `S` puts the task to sleep instead.
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::DerefMut,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::sched::{Scheduler, SpinWait};
+
+/// A lock guard that a [`Condvar`] can unlock while waiting and lock again afterwards.
+///
+/// # Safety
+///
+/// `unlock` must release the lock as dropping the guard would, `pop_off`
+/// included, and `relock` must take it back as locking would, `push_off`
+/// included, leaving the guard as valid as before.
+pub unsafe trait CondvarGuard {
+    /// Releases the lock but keeps the guard, which must not be used until `relock`.
+    #[doc(hidden)]
+    unsafe fn unlock(&mut self);
+
+    /// Takes the lock back after `unlock`.
+    #[doc(hidden)]
+    unsafe fn relock(&mut self);
+}
+
+pub struct Condvar<S = SpinWait> {
+    phantom: PhantomData<S>,
+    seq: AtomicUsize,
+}
+
+unsafe impl<S> Sync for Condvar<S> {}
+unsafe impl<S> Send for Condvar<S> {}
+
+impl Condvar {
+    /// Creates a spinning condition variable.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self::with_scheduler()
+    }
+}
+
+impl<S> Condvar<S> {
+    /// Creates a condition variable whose waiters sleep through `S`.
+    #[inline(always)]
+    pub const fn with_scheduler() -> Self {
+        Condvar {
+            phantom: PhantomData,
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn key(&self) -> usize {
+        &self.seq as *const AtomicUsize as usize
+    }
+}
+
+impl<S: Scheduler> Condvar<S> {
+    /// Unlocks `guard`'s lock, waits for a notification and locks it again.
+    ///
+    /// Wakeups may be spurious, so the condition must be checked again on
+    /// return, or use [`wait_while`](Condvar::wait_while).
+    pub fn wait<G: CondvarGuard>(&self, mut guard: G) -> G {
+        // Still under the lock, so a notifier that saw our condition false has not bumped it yet.
+        let seq = self.seq.load(Ordering::Relaxed);
+        unsafe { guard.unlock() };
+        while self.seq.load(Ordering::Acquire) == seq {
+            S::block(self.key(), &mut || self.seq.load(Ordering::Acquire) == seq);
+        }
+        unsafe { guard.relock() };
+        guard
+    }
+
+    /// Waits as long as `condition` returns `true` on the protected data.
+    pub fn wait_while<T, G, F>(&self, mut guard: G, mut condition: F) -> G
+    where
+        T: ?Sized,
+        G: CondvarGuard + DerefMut<Target = T>,
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wakes up one waiter. Spinning waiters may all wake up.
+    #[inline]
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        S::wake(self.key(), 1);
+    }
+
+    /// Wakes up every waiter.
+    #[inline]
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        S::wake(self.key(), usize::MAX);
+    }
+}
+
+impl<S> Default for Condvar<S> {
+    fn default() -> Self {
+        Self::with_scheduler()
+    }
+}
+
+impl<S> fmt::Debug for Condvar<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Condvar {{ .. }}")
+    }
+}
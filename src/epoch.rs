@@ -0,0 +1,185 @@
+//! Epoch-based memory reclamation for lock-free data structures.
+//!
+//! A cpu [`pin`]s itself before it loads pointers out of a shared structure.
+//! Memory unlinked from the structure is handed to [`Guard::defer_destroy`],
+//! tagged with the global epoch, and freed once the global epoch has advanced
+//! twice past it: by then every cpu that could have loaded the pointer has
+//! unpinned.
+//!
+//! The global epoch only advances when every pinned cpu has caught up with
+//! it, so a cpu must not stay pinned for long. Pinning turns off interrupts,
+//! which also keeps the pinned code from being preempted.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt,
+    marker::PhantomData,
+    mem,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupt::{cpu_id, mycpu, pop_off, push_off, shared_cpu, MAX_CORE_NUM},
+    spin::SpinMutex,
+};
+
+static GLOBAL_EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+const PINNED: usize = 1;
+
+// Collect once this many objects are waiting to be freed.
+const COLLECT_THRESHOLD: usize = 64;
+
+struct Deferred {
+    epoch: usize, // Global epoch when it was deferred.
+    func: Box<dyn FnOnce() + Send>,
+}
+
+static GARBAGE: SpinMutex<Vec<Deferred>> = SpinMutex::new(Vec::new());
+
+/// Witness that the current cpu is pinned, which it stays until every guard is dropped.
+///
+/// Pointers loaded while pinned stay valid as long as the guard lives.
+pub struct Guard {
+    _not_send: PhantomData<*mut ()>,
+}
+
+/// Pins the current cpu.
+///
+/// Pins nest; interrupts stay off until the outermost guard is dropped.
+#[inline]
+pub fn pin() -> Guard {
+    push_off();
+    let mut cpu = mycpu();
+    cpu.epoch_pins += 1;
+    if cpu.epoch_pins == 1 {
+        let epoch = GLOBAL_EPOCH.load(Ordering::Relaxed);
+        shared_cpu(cpu_id() as usize)
+            .epoch
+            .store(epoch << 1 | PINNED, Ordering::Relaxed);
+        // Make the pin visible before any pointer is loaded, pairs with `try_advance`.
+        fence(Ordering::SeqCst);
+    }
+    Guard {
+        _not_send: PhantomData,
+    }
+}
+
+/// Returns `true` if the current cpu is pinned.
+pub fn is_pinned() -> bool {
+    push_off();
+    let pinned = mycpu().epoch_pins > 0;
+    pop_off();
+    pinned
+}
+
+/// Tries to advance the global epoch, returning its value.
+///
+/// The epoch advances only if every pinned cpu is pinned in the current one.
+pub fn try_advance() -> usize {
+    let epoch = GLOBAL_EPOCH.load(Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+    for cpu in 0..MAX_CORE_NUM {
+        let local = shared_cpu(cpu).epoch.load(Ordering::Relaxed);
+        if local & PINNED != 0 && local >> 1 != epoch {
+            return epoch;
+        }
+    }
+    fence(Ordering::Acquire);
+    match GLOBAL_EPOCH.compare_exchange(
+        epoch,
+        epoch.wrapping_add(1),
+        Ordering::Release,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => epoch.wrapping_add(1),
+        Err(x) => x,
+    }
+}
+
+/// Tries to advance the global epoch and frees what no cpu can reach anymore.
+pub fn collect() {
+    let epoch = try_advance();
+    let ready: Vec<Deferred> = {
+        let mut garbage = GARBAGE.lock();
+        let (ready, pending) = mem::take(&mut *garbage)
+            .into_iter()
+            .partition(|deferred| epoch.wrapping_sub(deferred.epoch) as isize >= 2);
+        *garbage = pending;
+        ready
+    };
+    // Run them without holding the lock, they may defer more work.
+    for deferred in ready {
+        (deferred.func)();
+    }
+}
+
+impl Guard {
+    /// Runs `func` once no cpu pinned now can still be pinned.
+    pub fn defer<F: FnOnce() + Send + 'static>(&self, func: F) {
+        // Order the caller's unlink before reading the epoch, or a stale epoch
+        // could free the garbage while a cpu pinned in the next one reaches it.
+        fence(Ordering::SeqCst);
+        let epoch = GLOBAL_EPOCH.load(Ordering::Relaxed);
+        let len = {
+            let mut garbage = GARBAGE.lock();
+            garbage.push(Deferred {
+                epoch,
+                func: Box::new(func),
+            });
+            garbage.len()
+        };
+        if len >= COLLECT_THRESHOLD {
+            collect();
+        }
+    }
+
+    /// Frees `ptr` once no cpu pinned now can still be pinned.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must be unreachable for cpus that
+    /// pin from now on, and must not be freed by anything else.
+    pub unsafe fn defer_destroy<T: Send + 'static>(&self, ptr: *mut T) {
+        let ptr = Deferrable(ptr);
+        self.defer(move || drop(Box::from_raw(ptr.into_inner())));
+    }
+
+    /// Tries to advance the global epoch and frees what no cpu can reach anymore.
+    pub fn flush(&self) {
+        collect();
+    }
+}
+
+// Raw pointer handed to another cpu for freeing.
+struct Deferrable<T>(*mut T);
+
+// #Safety: Only the cpu running the deferred function accesses it.
+unsafe impl<T: Send> Send for Deferrable<T> {}
+
+impl<T> Deferrable<T> {
+    fn into_inner(self) -> *mut T {
+        self.0
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let mut cpu = mycpu();
+        cpu.epoch_pins -= 1;
+        if cpu.epoch_pins == 0 {
+            // Keep the accesses of the pinned section before the unpin.
+            shared_cpu(cpu_id() as usize)
+                .epoch
+                .store(0, Ordering::Release);
+        }
+        drop(cpu);
+        pop_off();
+    }
+}
+
+impl fmt::Debug for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Guard {{ .. }}")
+    }
+}
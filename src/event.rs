@@ -0,0 +1,143 @@
+//! An event async tasks wait for, set and reset by hand or reset on wakeup.
+//!
+//! Setting a manual-reset event wakes every waiter, and the event stays set,
+//! letting waiters through, until [`Event::reset`]. Setting an auto-reset
+//! event lets exactly one waiter through: the one that has waited longest, or
+//! the next one to come if nobody waits.
+//!
+//! Interrupt handlers may set events; see [the crate docs](crate#signalling-from-interrupt-handlers).
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{spin::SpinMutex, waker_list::WakerList};
+
+struct State {
+    set: bool,
+    waiters: WakerList,
+}
+
+impl State {
+    fn set(&mut self, auto_reset: bool) {
+        if !auto_reset {
+            self.set = true;
+            self.waiters.wake_all();
+        } else if !self.waiters.wake_front() {
+            self.set = true;
+        }
+    }
+}
+
+pub struct Event {
+    auto_reset: bool,
+    state: SpinMutex<State>,
+}
+
+/// The future returned by [`Event::wait`].
+pub struct EventWait<'a> {
+    event: &'a Event,
+    id: Option<usize>, // Our place in the queue, once we have one.
+}
+
+impl Event {
+    /// Creates an event that stays set until it is reset.
+    #[inline(always)]
+    pub const fn manual_reset(set: bool) -> Self {
+        Self::new(false, set)
+    }
+
+    /// Creates an event that resets as it lets a single waiter through.
+    #[inline(always)]
+    pub const fn auto_reset(set: bool) -> Self {
+        Self::new(true, set)
+    }
+
+    const fn new(auto_reset: bool, set: bool) -> Self {
+        Event {
+            auto_reset,
+            state: SpinMutex::new(State {
+                set,
+                waiters: WakerList::new(),
+            }),
+        }
+    }
+
+    /// Returns a future that resolves once the event is set.
+    pub fn wait(&self) -> EventWait {
+        EventWait {
+            event: self,
+            id: None,
+        }
+    }
+
+    /// Sets the event, waking the waiters it lets through.
+    pub fn set(&self) {
+        self.state.lock().set(self.auto_reset);
+    }
+
+    pub fn reset(&self) {
+        self.state.lock().set = false;
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.state.lock().set
+    }
+
+    pub fn is_auto_reset(&self) -> bool {
+        self.auto_reset
+    }
+}
+
+impl<'a> Future for EventWait<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let event = self.event;
+        let mut state = event.state.lock();
+        match self.id {
+            None if state.set => {
+                if event.auto_reset {
+                    state.set = false;
+                }
+                return Poll::Ready(());
+            }
+            None => self.id = Some(state.waiters.push((), cx.waker())),
+            // Woken by `set`, even if the event was reset since.
+            Some(id) if state.waiters.take_picked(id) => {
+                self.id = None;
+                return Poll::Ready(());
+            }
+            Some(id) => state.waiters.update(id, cx.waker()),
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a> Drop for EventWait<'a> {
+    /// Leaves the queue, passing an auto-reset wakeup on if it picked us.
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.event.state.lock();
+            if state.waiters.remove(id).is_some() && self.event.auto_reset {
+                state.set(true);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock();
+        write!(
+            f,
+            "Event {{ auto_reset: {}, set: {}, waiters: {} }}",
+            self.auto_reset,
+            state.set,
+            state.waiters.waiting()
+        )
+    }
+}
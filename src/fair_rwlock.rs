@@ -0,0 +1,496 @@
+//! A phase-fair reader-writer lock.
+//!
+//! This is the phase-fair ticket lock (PF-T) of Brandenburg and Anderson.
+//! Writers are served in ticket order, and a writer announces itself in the
+//! reader entry counter so that readers arriving after it wait. Readers and
+//! writers thus alternate in phases: a waiting writer enters once the readers
+//! ahead of it leave, and readers blocked by a writer all enter as soon as it
+//! releases, before the next writer.
+//!
+//! An upgradeable reader holds the writer ticket without announcing itself, so
+//! it excludes writers and other upgradeable readers but lets readers in until
+//! it upgrades.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    mem,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::interrupt::{pop_off, push_off};
+
+pub struct FairRwLock<T: ?Sized> {
+    rin: AtomicUsize,  // Readers entered, plus the writer bits.
+    rout: AtomicUsize, // Readers left.
+    win: AtomicUsize,  // Next writer ticket.
+    wout: AtomicUsize, // Writer ticket being served.
+    data: UnsafeCell<T>,
+}
+
+const READER: usize = 1 << 8;
+const PRESENT: usize = 1 << 1;
+const PHASE_ID: usize = 1;
+const WRITER_BITS: usize = PRESENT | PHASE_ID;
+
+/// A guard that provides immutable data access.
+///
+/// When the guard falls out of scope it will release the shared access.
+pub struct FairRwLockReadGuard<'a, T: 'a + ?Sized> {
+    inner: &'a FairRwLock<T>,
+    data: &'a T,
+}
+
+/// A guard that provides mutable data access.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct FairRwLockWriteGuard<'a, T: 'a + ?Sized> {
+    inner: &'a FairRwLock<T>,
+    ticket: usize,
+    data: &'a mut T,
+}
+
+/// A guard that provides immutable data access but can be upgraded to [`FairRwLockWriteGuard`].
+///
+/// No writers or other upgradeable guards can exist while this is in scope, but
+/// readers may still enter until it is upgraded.
+///
+/// When the guard falls out of scope it will release the lock.
+pub struct FairRwLockUpgradableGuard<'a, T: 'a + ?Sized> {
+    inner: &'a FairRwLock<T>,
+    ticket: usize,
+    data: &'a T,
+}
+
+// Same unsafe impls as `std::sync::RwLock`
+unsafe impl<T: ?Sized + Send> Send for FairRwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for FairRwLock<T> {}
+
+impl<T> FairRwLock<T> {
+    /// Creates a new phase-fair lock wrapping the supplied data.
+    #[inline]
+    pub const fn new(data: T) -> Self {
+        FairRwLock {
+            rin: AtomicUsize::new(0),
+            rout: AtomicUsize::new(0),
+            win: AtomicUsize::new(0),
+            wout: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Consumes this `FairRwLock`, returning the underlying data.
+    #[inline]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        let FairRwLock { data, .. } = self;
+        data.into_inner()
+    }
+
+    /// Returns a mutable pointer to the underying data.
+    ///
+    /// While this is safe, writing to the data is undefined behavior unless the current thread has
+    /// acquired a write lock, and reading requires either a read or write lock.
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized> FairRwLock<T> {
+    /// Locks this rwlock with shared read access, blocking the current thread
+    /// until it can be acquired.
+    ///
+    /// A reader arriving while a writer holds or waits for the lock waits for
+    /// that writer's phase to end, and then enters before any later writer.
+    #[inline]
+    pub fn read(&self) -> FairRwLockReadGuard<T> {
+        push_off();
+        let writer = self.rin.fetch_add(READER, Ordering::Acquire) & WRITER_BITS;
+        if writer != 0 {
+            while self.rin.load(Ordering::Acquire) & WRITER_BITS == writer {
+                spin_loop();
+            }
+        }
+        FairRwLockReadGuard {
+            inner: self,
+            data: unsafe { &*self.data.get() },
+        }
+    }
+
+    /// Lock this rwlock with exclusive write access, blocking the current
+    /// thread until it can be acquired.
+    ///
+    /// Writers are served in FIFO order. Once it is first in line, a writer
+    /// blocks new readers and waits for the current ones to leave.
+    #[inline]
+    pub fn write(&self) -> FairRwLockWriteGuard<T> {
+        push_off();
+        let ticket = self.wait_ticket();
+        self.enter_writer(ticket);
+        FairRwLockWriteGuard {
+            inner: self,
+            ticket,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Obtain a readable lock guard that can later be upgraded to a writable lock guard.
+    /// Upgrades can be done through the [`FairRwLockUpgradableGuard::upgrade`](FairRwLockUpgradableGuard::upgrade) method.
+    #[inline]
+    pub fn upgradeable_read(&self) -> FairRwLockUpgradableGuard<T> {
+        push_off();
+        let ticket = self.wait_ticket();
+        FairRwLockUpgradableGuard {
+            inner: self,
+            ticket,
+            data: unsafe { &*self.data.get() },
+        }
+    }
+
+    /// Attempt to acquire this lock with shared read access.
+    ///
+    /// Returns `None` if a writer holds or waits for the lock.
+    #[inline]
+    pub fn try_read(&self) -> Option<FairRwLockReadGuard<T>> {
+        push_off();
+        // Never take a place in `rin` we may have to give back, a waiting
+        // writer only counts the readers that entered before it.
+        let result = self
+            .rin
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |rin| {
+                if rin & WRITER_BITS == 0 {
+                    Some(rin.wrapping_add(READER))
+                } else {
+                    None
+                }
+            });
+        if result.is_ok() {
+            Some(FairRwLockReadGuard {
+                inner: self,
+                data: unsafe { &*self.data.get() },
+            })
+        } else {
+            pop_off();
+            None
+        }
+    }
+
+    /// Attempt to lock this rwlock with exclusive write access.
+    ///
+    /// Returns `None` if a call to `write` would otherwise block.
+    #[inline]
+    pub fn try_write(&self) -> Option<FairRwLockWriteGuard<T>> {
+        push_off();
+        let ticket = match self.try_ticket() {
+            Some(ticket) => ticket,
+            None => {
+                pop_off();
+                return None;
+            }
+        };
+        if self.try_enter_writer(ticket) {
+            Some(FairRwLockWriteGuard {
+                inner: self,
+                ticket,
+                data: unsafe { &mut *self.data.get() },
+            })
+        } else {
+            self.wout.fetch_add(1, Ordering::Release);
+            pop_off();
+            None
+        }
+    }
+
+    /// Tries to obtain an upgradeable lock guard.
+    #[inline]
+    pub fn try_upgradeable_read(&self) -> Option<FairRwLockUpgradableGuard<T>> {
+        push_off();
+        match self.try_ticket() {
+            Some(ticket) => Some(FairRwLockUpgradableGuard {
+                inner: self,
+                ticket,
+                data: unsafe { &*self.data.get() },
+            }),
+            None => {
+                pop_off();
+                None
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the `FairRwLock` mutably, no actual locking needs to
+    /// take place -- the mutable borrow statically guarantees no locks exist.
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner lock.
+        unsafe { &mut *self.data.get() }
+    }
+
+    /// Takes a writer ticket and waits for it to be served.
+    #[inline(always)]
+    fn wait_ticket(&self) -> usize {
+        let ticket = self.win.fetch_add(1, Ordering::Relaxed);
+        while self.wout.load(Ordering::Acquire) != ticket {
+            spin_loop();
+        }
+        ticket
+    }
+
+    /// Takes a writer ticket only if it would be served right away.
+    #[inline(always)]
+    fn try_ticket(&self) -> Option<usize> {
+        self.win
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |ticket| {
+                if self.wout.load(Ordering::Acquire) == ticket {
+                    Some(ticket.wrapping_add(1))
+                } else {
+                    None
+                }
+            })
+            .ok()
+    }
+
+    /// Blocks new readers and waits for the current ones to leave.
+    #[inline(always)]
+    fn enter_writer(&self, ticket: usize) {
+        let readers = self.announce_writer(ticket);
+        while self.rout.load(Ordering::Acquire) != readers {
+            spin_loop();
+        }
+    }
+
+    /// Like `enter_writer`, but lets the readers in again if any are still inside.
+    #[inline(always)]
+    fn try_enter_writer(&self, ticket: usize) -> bool {
+        let readers = self.announce_writer(ticket);
+        if self.rout.load(Ordering::Acquire) == readers {
+            true
+        } else {
+            self.rin.fetch_and(!WRITER_BITS, Ordering::Release);
+            false
+        }
+    }
+
+    /// Sets the writer bits, returning the number of readers that entered before.
+    #[inline(always)]
+    fn announce_writer(&self, ticket: usize) -> usize {
+        let writer = PRESENT | (ticket & PHASE_ID);
+        self.rin.fetch_add(writer, Ordering::Acquire) & !WRITER_BITS
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for FairRwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => write!(f, "FairRwLock {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "FairRwLock {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: Default> Default for FairRwLock<T> {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl<T> From<T> for FairRwLock<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for FairRwLockReadGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for FairRwLockReadGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized> FairRwLockUpgradableGuard<'rwlock, T> {
+    /// Upgrades an upgradeable lock guard to a writable lock guard.
+    ///
+    /// New readers are blocked right away, and the call returns once the
+    /// readers already inside have left.
+    #[inline]
+    pub fn upgrade(self) -> FairRwLockWriteGuard<'rwlock, T> {
+        let inner = self.inner;
+        let ticket = self.ticket;
+        // Forget the old guard so its destructor doesn't run (before mutably aliasing data below)
+        mem::forget(self);
+        inner.enter_writer(ticket);
+        FairRwLockWriteGuard {
+            inner,
+            ticket,
+            data: unsafe { &mut *inner.data.get() },
+        }
+    }
+
+    /// Tries to upgrade an upgradeable lock guard to a writable lock guard.
+    ///
+    /// Fails if there are readers inside the lock.
+    #[inline]
+    pub fn try_upgrade(self) -> Result<FairRwLockWriteGuard<'rwlock, T>, Self> {
+        if self.inner.try_enter_writer(self.ticket) {
+            let inner = self.inner;
+            let ticket = self.ticket;
+            mem::forget(self);
+            Ok(FairRwLockWriteGuard {
+                inner,
+                ticket,
+                data: unsafe { &mut *inner.data.get() },
+            })
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Downgrades the upgradeable lock guard to a readable, shared lock guard. Cannot fail and is guaranteed not to spin.
+    #[inline]
+    pub fn downgrade(self) -> FairRwLockReadGuard<'rwlock, T> {
+        // No writer can be present while we hold the ticket, so we enter right away.
+        self.inner.rin.fetch_add(READER, Ordering::Acquire);
+        self.inner.wout.fetch_add(1, Ordering::Release);
+
+        let inner = self.inner;
+
+        // Interrupts stay off for the read guard
+        mem::forget(self);
+
+        FairRwLockReadGuard {
+            inner,
+            data: unsafe { &*inner.data.get() },
+        }
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for FairRwLockUpgradableGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for FairRwLockUpgradableGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized> FairRwLockWriteGuard<'rwlock, T> {
+    /// Downgrades the writable lock guard to a readable, shared lock guard. Cannot fail and is guaranteed not to spin.
+    #[inline]
+    pub fn downgrade(self) -> FairRwLockReadGuard<'rwlock, T> {
+        // Reserve the read guard for ourselves before letting the other readers in
+        self.inner.rin.fetch_add(READER, Ordering::Acquire);
+        self.inner.rin.fetch_and(!WRITER_BITS, Ordering::Release);
+        self.inner.wout.fetch_add(1, Ordering::Release);
+
+        let inner = self.inner;
+
+        // Interrupts stay off for the read guard
+        mem::forget(self);
+
+        FairRwLockReadGuard {
+            inner,
+            data: unsafe { &*inner.data.get() },
+        }
+    }
+
+    /// Downgrades the writable lock guard to an upgradable, shared lock guard. Cannot fail and is guaranteed not to spin.
+    #[inline]
+    pub fn downgrade_to_upgradeable(self) -> FairRwLockUpgradableGuard<'rwlock, T> {
+        // Let the readers in again, but keep the writer ticket
+        self.inner.rin.fetch_and(!WRITER_BITS, Ordering::Release);
+
+        let inner = self.inner;
+        let ticket = self.ticket;
+
+        mem::forget(self);
+
+        FairRwLockUpgradableGuard {
+            inner,
+            ticket,
+            data: unsafe { &*inner.data.get() },
+        }
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for FairRwLockWriteGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for FairRwLockWriteGuard<'rwlock, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for FairRwLockReadGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for FairRwLockUpgradableGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> Deref for FairRwLockWriteGuard<'rwlock, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> DerefMut for FairRwLockWriteGuard<'rwlock, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for FairRwLockReadGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        self.inner.rout.fetch_add(READER, Ordering::Release);
+        pop_off();
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for FairRwLockUpgradableGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        self.inner.wout.fetch_add(1, Ordering::Release);
+        pop_off();
+    }
+}
+
+impl<'rwlock, T: ?Sized> Drop for FairRwLockWriteGuard<'rwlock, T> {
+    fn drop(&mut self) {
+        debug_assert_ne!(self.inner.rin.load(Ordering::Relaxed) & PRESENT, 0);
+
+        // Readers blocked by us enter before the next writer is served.
+        self.inner.rin.fetch_and(!WRITER_BITS, Ordering::Release);
+        self.inner.wout.fetch_add(1, Ordering::Release);
+        pop_off();
+    }
+}
@@ -0,0 +1,381 @@
+//! The kernel side of futexes.
+//!
+//! Waiters are queued in a hash table of buckets keyed by the futex address,
+//! each bucket under its own spin lock. [`futex_wait`] checks the futex word
+//! under the bucket lock, so a waker that changes the word and then calls
+//! [`futex_wake`] either finds the waiter queued or makes it return
+//! [`FutexError::WouldBlock`], and no wakeup is lost.
+//!
+//! Each waiter sleeps through the [`Scheduler`] on a flag of its own rather
+//! than on the futex address, which lets [`futex_requeue`] move waiters to
+//! another futex without touching the scheduler.
+//!
+//! The futex words are given as `AtomicU32`s the kernel has already mapped;
+//! faulting in user pages is left to the caller.
+
+use alloc::vec::Vec;
+use core::{
+    fmt, ptr,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    sched::Scheduler,
+    spin::{SpinMutex, SpinMutexGuard},
+};
+
+const FUTEX_BUCKET_BITS: u32 = 8;
+pub const FUTEX_BUCKETS: usize = 1 << FUTEX_BUCKET_BITS;
+
+/// A waiter, living on the stack of its task for as long as it is queued.
+struct FutexQ {
+    addr: AtomicUsize, // The futex we are queued on, changed by requeueing.
+    woken: AtomicBool,
+}
+
+impl FutexQ {
+    fn key(&self) -> usize {
+        &self.woken as *const AtomicBool as usize
+    }
+}
+
+struct Bucket {
+    waiters: Vec<*const FutexQ>, // In arrival order.
+}
+
+// #Safety: A waiter stays on its stack while it is queued, and wakers take it off under the
+// bucket lock, touching it last when they set `woken`.
+unsafe impl Send for Bucket {}
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_BUCKET: SpinMutex<Bucket> = SpinMutex::new(Bucket {
+    waiters: Vec::new(),
+});
+
+static BUCKETS: [SpinMutex<Bucket>; FUTEX_BUCKETS] = [DEFAULT_BUCKET; FUTEX_BUCKETS];
+
+// The golden ratio as a fraction of the `usize` range, for Fibonacci hashing.
+const GOLDEN_RATIO: usize = (0x9e37_79b9_7f4a_7c15_u64 >> (64 - usize::BITS)) as usize;
+
+fn hash(addr: usize) -> usize {
+    // The top bits of the product depend on every bit of the address.
+    addr.wrapping_mul(GOLDEN_RATIO) >> (usize::BITS - FUTEX_BUCKET_BITS)
+}
+
+fn bucket(addr: usize) -> SpinMutexGuard<'static, Bucket> {
+    BUCKETS[hash(addr)].lock()
+}
+
+/// Locks the buckets of two futexes in a fixed order. The second guard is
+/// `None` if both share a bucket.
+fn double_bucket(
+    addr1: usize,
+    addr2: usize,
+) -> (
+    SpinMutexGuard<'static, Bucket>,
+    Option<SpinMutexGuard<'static, Bucket>>,
+) {
+    let (hash1, hash2) = (hash(addr1), hash(addr2));
+    if hash1 == hash2 {
+        (BUCKETS[hash1].lock(), None)
+    } else if hash1 < hash2 {
+        let first = BUCKETS[hash1].lock();
+        (first, Some(BUCKETS[hash2].lock()))
+    } else {
+        let second = BUCKETS[hash2].lock();
+        (BUCKETS[hash1].lock(), Some(second))
+    }
+}
+
+fn futex_key(futex: &AtomicU32) -> usize {
+    futex as *const AtomicU32 as usize
+}
+
+/// Takes up to `n` waiters on `addr` off `bucket` and wakes them.
+fn wake_waiters<S: Scheduler>(bucket: &mut Bucket, addr: usize, mut n: usize) -> usize {
+    let mut woken = 0;
+    bucket.waiters.retain(|q| {
+        let q = unsafe { &**q };
+        if n == 0 || q.addr.load(Ordering::Relaxed) != addr {
+            return true;
+        }
+        n -= 1;
+        let key = q.key();
+        // The waiter may return as soon as it sees this, so `q` is not ours
+        // past it.
+        q.woken.store(true, Ordering::Release);
+        S::wake(key, 1);
+        woken += 1;
+        false
+    });
+    woken
+}
+
+/// The error returned by a futex operation that did not wait or wake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexError {
+    /// The futex word did not hold the expected value, like `EAGAIN`.
+    WouldBlock,
+    /// The timeout ran out before a wakeup, like `ETIMEDOUT`.
+    TimedOut,
+}
+
+impl fmt::Display for FutexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FutexError::WouldBlock => write!(f, "futex word changed"),
+            FutexError::TimedOut => write!(f, "futex wait timed out"),
+        }
+    }
+}
+
+/// Sleeps on `futex` if it still holds `expected`, until a wakeup.
+pub fn futex_wait<S: Scheduler>(futex: &AtomicU32, expected: u32) -> Result<(), FutexError> {
+    wait::<S>(futex, expected, None)
+}
+
+/// Like [`futex_wait`], but gives up after `timeout`.
+pub fn futex_wait_timeout<S: Scheduler>(
+    futex: &AtomicU32,
+    expected: u32,
+    timeout: Duration,
+) -> Result<(), FutexError> {
+    wait::<S>(futex, expected, Some(timeout))
+}
+
+fn wait<S: Scheduler>(
+    futex: &AtomicU32,
+    expected: u32,
+    mut timeout: Option<Duration>,
+) -> Result<(), FutexError> {
+    let addr = futex_key(futex);
+    let q = FutexQ {
+        addr: AtomicUsize::new(addr),
+        woken: AtomicBool::new(false),
+    };
+    {
+        let mut bucket = bucket(addr);
+        // Wakers change the word before taking the bucket lock.
+        if futex.load(Ordering::SeqCst) != expected {
+            return Err(FutexError::WouldBlock);
+        }
+        bucket.waiters.push(&q);
+    }
+    let mut should_block = || !q.woken.load(Ordering::Acquire);
+    loop {
+        match timeout {
+            Some(left) if left == Duration::ZERO => break,
+            Some(left) => timeout = Some(S::block_timeout(q.key(), &mut should_block, left)),
+            None => S::block(q.key(), &mut should_block),
+        }
+        if q.woken.load(Ordering::Acquire) {
+            return Ok(());
+        }
+    }
+    // Timed out. Leave whichever bucket we have been requeued to, unless a
+    // wakeup got there first.
+    loop {
+        let addr = q.addr.load(Ordering::Relaxed);
+        let mut bucket = bucket(addr);
+        if q.addr.load(Ordering::Relaxed) != addr {
+            continue;
+        }
+        let queued = bucket.waiters.iter().position(|w| ptr::eq(*w, &q));
+        return match queued {
+            Some(index) => {
+                bucket.waiters.remove(index);
+                Err(FutexError::TimedOut)
+            }
+            None => Ok(()),
+        };
+    }
+}
+
+/// Wakes up to `n` tasks waiting on `futex`, returning how many were woken.
+pub fn futex_wake<S: Scheduler>(futex: &AtomicU32, n: usize) -> usize {
+    let addr = futex_key(futex);
+    wake_waiters::<S>(&mut bucket(addr), addr, n)
+}
+
+/// Wakes up to `n_wake` tasks waiting on `futex` and moves up to `n_requeue`
+/// of the others over to `target`, if `futex` still holds `expected`, like
+/// `FUTEX_CMP_REQUEUE`.
+///
+/// Returns the number of tasks woken or moved.
+pub fn futex_requeue<S: Scheduler>(
+    futex: &AtomicU32,
+    n_wake: usize,
+    target: &AtomicU32,
+    mut n_requeue: usize,
+    expected: u32,
+) -> Result<usize, FutexError> {
+    let (addr, target_addr) = (futex_key(futex), futex_key(target));
+    let (mut bucket, mut target_bucket) = double_bucket(addr, target_addr);
+    if futex.load(Ordering::SeqCst) != expected {
+        return Err(FutexError::WouldBlock);
+    }
+    let mut done = wake_waiters::<S>(&mut bucket, addr, n_wake);
+    // Waiters stay put if `target` hashes to the same bucket.
+    let same_bucket = target_bucket.is_none();
+    let mut moved = Vec::new();
+    bucket.waiters.retain(|q| {
+        let waiter = unsafe { &**q };
+        if n_requeue == 0 || waiter.addr.load(Ordering::Relaxed) != addr {
+            return true;
+        }
+        n_requeue -= 1;
+        done += 1;
+        // Both bucket locks are held, so a timing-out waiter looks in the right one.
+        waiter.addr.store(target_addr, Ordering::Relaxed);
+        if !same_bucket {
+            moved.push(*q);
+        }
+        same_bucket
+    });
+    if let Some(target_bucket) = target_bucket.as_mut() {
+        target_bucket.waiters.append(&mut moved);
+    }
+    Ok(done)
+}
+
+/// An operation on a futex word, with the comparison deciding the second
+/// wakeup of [`futex_wake_op`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FutexOp {
+    pub op: FutexOpKind,
+    pub oparg: u32,
+    pub cmp: FutexCmp,
+    pub cmparg: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexOpKind {
+    Set,
+    Add,
+    Or,
+    AndNot,
+    Xor,
+}
+
+/// Comparisons of the old value of the word, as signed integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FutexCmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FutexOp {
+    pub const fn new(op: FutexOpKind, oparg: u32, cmp: FutexCmp, cmparg: u32) -> Self {
+        FutexOp {
+            op,
+            oparg,
+            cmp,
+            cmparg,
+        }
+    }
+
+    /// Decodes the `FUTEX_OP()` encoding of Linux, with its 12-bit arguments.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        let op = match (bits >> 28) & 7 {
+            0 => FutexOpKind::Set,
+            1 => FutexOpKind::Add,
+            2 => FutexOpKind::Or,
+            3 => FutexOpKind::AndNot,
+            4 => FutexOpKind::Xor,
+            _ => return None,
+        };
+        let cmp = match (bits >> 24) & 15 {
+            0 => FutexCmp::Eq,
+            1 => FutexCmp::Ne,
+            2 => FutexCmp::Lt,
+            3 => FutexCmp::Le,
+            4 => FutexCmp::Gt,
+            5 => FutexCmp::Ge,
+            _ => return None,
+        };
+        // The arguments are sign-extended 12-bit fields.
+        let oparg = ((bits << 8) as i32 >> 20) as u32;
+        let cmparg = ((bits << 20) as i32 >> 20) as u32;
+        // FUTEX_OP_OPARG_SHIFT: the argument is a shift count.
+        let oparg = if bits & (8 << 28) != 0 {
+            1u32.checked_shl(oparg)?
+        } else {
+            oparg
+        };
+        Some(FutexOp::new(op, oparg, cmp, cmparg))
+    }
+
+    fn apply(&self, old: u32) -> u32 {
+        match self.op {
+            FutexOpKind::Set => self.oparg,
+            FutexOpKind::Add => old.wrapping_add(self.oparg),
+            FutexOpKind::Or => old | self.oparg,
+            FutexOpKind::AndNot => old & !self.oparg,
+            FutexOpKind::Xor => old ^ self.oparg,
+        }
+    }
+
+    fn compare(&self, old: u32) -> bool {
+        let (old, arg) = (old as i32, self.cmparg as i32);
+        match self.cmp {
+            FutexCmp::Eq => old == arg,
+            FutexCmp::Ne => old != arg,
+            FutexCmp::Lt => old < arg,
+            FutexCmp::Le => old <= arg,
+            FutexCmp::Gt => old > arg,
+            FutexCmp::Ge => old >= arg,
+        }
+    }
+}
+
+/// Applies `op` to `futex2`, wakes up to `n1` tasks waiting on `futex1`, and
+/// if the old value of `futex2` passes the comparison of `op`, wakes up to
+/// `n2` tasks waiting on `futex2` as well.
+///
+/// Returns the number of tasks woken.
+pub fn futex_wake_op<S: Scheduler>(
+    futex1: &AtomicU32,
+    n1: usize,
+    futex2: &AtomicU32,
+    n2: usize,
+    op: FutexOp,
+) -> usize {
+    let (addr1, addr2) = (futex_key(futex1), futex_key(futex2));
+    let (mut bucket1, mut bucket2) = double_bucket(addr1, addr2);
+    let old = futex2
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+            Some(op.apply(old))
+        })
+        .unwrap();
+    let mut woken = wake_waiters::<S>(&mut bucket1, addr1, n1);
+    if op.compare(old) {
+        let bucket2 = match bucket2.as_mut() {
+            Some(bucket2) => bucket2,
+            None => &mut bucket1,
+        };
+        woken += wake_waiters::<S>(bucket2, addr2, n2);
+    }
+    woken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_test() {
+        // The same word in the stacks or TLS blocks of a few threads, 256 KiB
+        // apart, lands in different buckets.
+        let mut used = [false; FUTEX_BUCKETS];
+        for i in 0..32 {
+            used[hash(0x7f00_1000 + i * (256 << 10))] = true;
+        }
+        assert_eq!(used.iter().filter(|used| **used).count(), 32);
+    }
+}
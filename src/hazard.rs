@@ -0,0 +1,243 @@
+//! Hazard pointers for lock-free data structures.
+//!
+//! Before dereferencing a shared pointer, a cpu publishes it in one of its
+//! hazard slots. Memory unlinked from the structure is retired to the domain,
+//! and once enough of it piles up a scan frees whatever no slot protects.
+//!
+//! Unlike epochs, a reader stuck with a pointer protected only keeps that one
+//! object alive, so a cpu sitting with interrupts off cannot hold back the
+//! reclamation of everything else.
+//!
+//! A `HazardPointer` keeps interrupts off while it lives, as its slot belongs
+//! to the current cpu. So interrupt handlers only run on a cpu holding no
+//! hazard pointers, and find all its slots free.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt,
+    hint::spin_loop,
+    mem, ptr,
+    sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupt::{cpu_id, pop_off, push_off, MAX_CORE_NUM},
+    spin::SpinMutex,
+};
+
+/// How many hazard pointers each cpu can hold at once in a domain.
+pub const HAZARDS_PER_CPU: usize = 8;
+
+/// Scan once this many objects have been retired, unless told otherwise.
+pub const DEFAULT_SCAN_THRESHOLD: usize = 64;
+
+#[repr(align(64))]
+struct HazardCpu {
+    used: AtomicUsize, // Bitmap of the slots taken, only changed by this cpu.
+    hazards: [AtomicPtr<u8>; HAZARDS_PER_CPU],
+}
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_HAZARD: AtomicPtr<u8> = AtomicPtr::new(ptr::null_mut());
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_HAZARD_CPU: HazardCpu = HazardCpu {
+    used: AtomicUsize::new(0),
+    hazards: [DEFAULT_HAZARD; HAZARDS_PER_CPU],
+};
+
+struct Retired {
+    ptr: *mut u8,
+    free: unsafe fn(*mut u8),
+}
+
+// #Safety: `retire` only accepts `Send` objects.
+unsafe impl Send for Retired {}
+
+unsafe fn free_box<T>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut T));
+}
+
+pub struct HazardDomain {
+    cpus: [HazardCpu; MAX_CORE_NUM],
+    retired: SpinMutex<Vec<Retired>>,
+    threshold: usize,
+}
+
+/// A hazard slot of the current cpu.
+///
+/// When it falls out of scope, the slot is cleared and interrupts are restored.
+pub struct HazardPointer<'a> {
+    cpu: &'a HazardCpu,
+    index: usize,
+}
+
+unsafe impl Sync for HazardDomain {}
+unsafe impl Send for HazardDomain {}
+
+impl HazardDomain {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self::with_threshold(DEFAULT_SCAN_THRESHOLD)
+    }
+
+    /// Creates a domain that scans once `threshold` objects have been retired.
+    #[inline(always)]
+    pub const fn with_threshold(threshold: usize) -> Self {
+        HazardDomain {
+            cpus: [DEFAULT_HAZARD_CPU; MAX_CORE_NUM],
+            retired: SpinMutex::new(Vec::new()),
+            threshold,
+        }
+    }
+
+    /// Takes a free hazard slot of the current cpu.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cpu already holds `HAZARDS_PER_CPU` hazard pointers.
+    pub fn hazard(&self) -> HazardPointer {
+        push_off();
+        let cpu = &self.cpus[cpu_id() as usize];
+        let used = cpu.used.load(Ordering::Relaxed);
+        let index = (!used).trailing_zeros() as usize;
+        assert!(index < HAZARDS_PER_CPU, "too many hazard pointers");
+        cpu.used.store(used | 1 << index, Ordering::Relaxed);
+        HazardPointer { cpu, index }
+    }
+
+    /// Loads `src` and protects what it points to until the hazard pointer is dropped.
+    pub fn protect<T>(&self, src: &AtomicPtr<T>) -> (HazardPointer, *mut T) {
+        let hazard = self.hazard();
+        let ptr = hazard.protect(src);
+        (hazard, ptr)
+    }
+
+    /// Retires `ptr`, to be freed once no hazard pointer protects it, as late
+    /// as when the domain is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must be unreachable for loads
+    /// from now on, and must not be retired or freed by anything else.
+    pub unsafe fn retire<T: Send + 'static>(&self, ptr: *mut T) {
+        let len = {
+            let mut retired = self.retired.lock();
+            retired.push(Retired {
+                ptr: ptr as *mut u8,
+                free: free_box::<T>,
+            });
+            retired.len()
+        };
+        if len >= self.threshold {
+            self.scan();
+        }
+    }
+
+    /// Frees every retired object that no hazard pointer protects.
+    pub fn scan(&self) {
+        let retired = mem::take(&mut *self.retired.lock());
+        if retired.is_empty() {
+            return;
+        }
+        // Order the unlinking of the retired objects before reading the hazards.
+        fence(Ordering::SeqCst);
+        let mut hazards: Vec<*mut u8> = self
+            .cpus
+            .iter()
+            .flat_map(|cpu| cpu.hazards.iter())
+            .map(|hazard| hazard.load(Ordering::Acquire))
+            .filter(|hazard| !hazard.is_null())
+            .collect();
+        hazards.sort_unstable();
+        let mut kept = Vec::new();
+        for object in retired {
+            if hazards.binary_search(&object.ptr).is_ok() {
+                kept.push(object);
+            } else {
+                unsafe { (object.free)(object.ptr) };
+            }
+        }
+        if !kept.is_empty() {
+            self.retired.lock().append(&mut kept);
+        }
+    }
+
+    /// Returns the number of retired objects not freed yet.
+    pub fn retired_count(&self) -> usize {
+        self.retired.lock().len()
+    }
+}
+
+impl<'a> HazardPointer<'a> {
+    /// Loads `src` and protects what it points to, dropping what this hazard
+    /// pointer protected before.
+    pub fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load(Ordering::Relaxed);
+        loop {
+            self.set(ptr);
+            // Publish the hazard before checking that `ptr` is still reachable,
+            // pairs with the fence in `scan`.
+            fence(Ordering::SeqCst);
+            let again = src.load(Ordering::Acquire);
+            if again == ptr {
+                return ptr;
+            }
+            ptr = again;
+            spin_loop();
+        }
+    }
+
+    /// Protects `ptr`, which the caller must check is still reachable afterwards.
+    #[inline(always)]
+    pub fn set<T>(&self, ptr: *mut T) {
+        self.cpu.hazards[self.index].store(ptr as *mut u8, Ordering::Release);
+    }
+
+    /// Stops protecting anything.
+    #[inline(always)]
+    pub fn reset(&self) {
+        self.cpu.hazards[self.index].store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl Default for HazardDomain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardDomain {
+    fn drop(&mut self) {
+        // No hazard pointer borrows the domain anymore.
+        for object in mem::take(self.retired.get_mut()) {
+            unsafe { (object.free)(object.ptr) };
+        }
+    }
+}
+
+impl fmt::Debug for HazardDomain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HazardDomain {{ retired: {} }}", self.retired_count())
+    }
+}
+
+impl<'a> Drop for HazardPointer<'a> {
+    fn drop(&mut self) {
+        self.reset();
+        self.cpu
+            .used
+            .fetch_and(!(1 << self.index), Ordering::Relaxed);
+        pop_off();
+    }
+}
+
+impl<'a> fmt::Debug for HazardPointer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "HazardPointer {{ {:p} }}",
+            self.cpu.hazards[self.index].load(Ordering::Relaxed)
+        )
+    }
+}
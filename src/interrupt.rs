@@ -1,4 +1,8 @@
-use core::cell::{RefCell, RefMut};
+use core::{
+    cell::{RefCell, RefMut},
+    mem,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "none", any(target_arch = "riscv32", target_arch = "riscv64")))] {
@@ -73,6 +77,78 @@ cfg_if::cfg_if! {
                 !DAIF.is_set(DAIF::I)
             }
         }
+    } else if #[cfg(not(target_os = "none"))] {
+        // Hosted simulation: every std thread stands in for a CPU. A thread
+        // claims a free cpu id the first time it asks for one and hands it back
+        // when it exits, so at most MAX_CORE_NUM such threads may be live at
+        // once. The interrupt-enable flag is kept per thread.
+        mod interrupts {
+            extern crate std;
+            use core::{
+                cell::Cell,
+                sync::atomic::{AtomicUsize, Ordering},
+            };
+
+            use super::MAX_CORE_NUM;
+
+            // Bitmap of the cpu ids currently claimed by a thread.
+            static ONLINE: AtomicUsize = AtomicUsize::new(0);
+
+            struct SimCpu(Cell<Option<u8>>);
+
+            impl Drop for SimCpu {
+                fn drop(&mut self) {
+                    if let Some(id) = self.0.get() {
+                        ONLINE.fetch_and(!(1 << id), Ordering::Release);
+                    }
+                }
+            }
+
+            std::thread_local! {
+                static SIM_CPU: SimCpu = const { SimCpu(Cell::new(None)) };
+                static SIM_INTR: Cell<bool> = const { Cell::new(true) };
+            }
+
+            fn claim_cpu() -> u8 {
+                loop {
+                    let online = ONLINE.load(Ordering::Relaxed);
+                    let id = (!online).trailing_zeros() as usize;
+                    // Waiting for a thread to exit could hang for good, as
+                    // nothing says the threads holding cpus will ever exit.
+                    assert!(
+                        id < MAX_CORE_NUM,
+                        "more than MAX_CORE_NUM ({}) simulated cpus: too many live threads use the lock",
+                        MAX_CORE_NUM
+                    );
+                    if ONLINE
+                        .compare_exchange_weak(online, online | 1 << id, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        return id as u8;
+                    }
+                }
+            }
+
+            pub(crate) fn cpu_id() -> u8 {
+                SIM_CPU.with(|cpu| match cpu.0.get() {
+                    Some(id) => id,
+                    None => {
+                        let id = claim_cpu();
+                        cpu.0.set(Some(id));
+                        id
+                    }
+                })
+            }
+            pub(crate) fn intr_on() {
+                SIM_INTR.with(|intr| intr.set(true));
+            }
+            pub(crate) fn intr_off() {
+                SIM_INTR.with(|intr| intr.set(false));
+            }
+            pub(crate) fn intr_get() -> bool {
+                SIM_INTR.with(|intr| intr.get())
+            }
+        }
     } else {
         mod interrupts {
             pub(crate) fn cpu_id() -> u8 {
@@ -87,13 +163,22 @@ cfg_if::cfg_if! {
     }
 }
 
-use interrupts::*;
+pub(crate) use interrupts::*;
+
+// How many RwLock read guards per cpu the nested read check keeps track of.
+#[cfg(debug_assertions)]
+pub(crate) const MAX_HELD_READS: usize = 8;
 
 #[derive(Debug, Default, Clone, Copy)]
 #[repr(align(64))]
 pub struct Cpu {
     pub noff: i32,              // Depth of push_off() nesting.
     pub interrupt_enable: bool, // Were interrupts enabled before push_off()?
+    pub rcu_nesting: i32,       // Depth of rcu_read_lock() nesting.
+    pub epoch_pins: i32,        // Depth of epoch::pin() nesting.
+    pub priority: usize,        // Priority level raised to by push_priority().
+    #[cfg(debug_assertions)]
+    pub held_reads: [usize; MAX_HELD_READS], // RwLocks read-locked on this cpu, 0 if free.
 }
 
 impl Cpu {
@@ -101,6 +186,11 @@ impl Cpu {
         Self {
             noff: 0,
             interrupt_enable: false,
+            rcu_nesting: 0,
+            epoch_pins: 0,
+            priority: 0,
+            #[cfg(debug_assertions)]
+            held_reads: [0; MAX_HELD_READS],
         }
     }
 }
@@ -120,7 +210,7 @@ impl<T> SafeRefCell<T> {
 #[allow(clippy::declare_interior_mutable_const)]
 const DEFAULT_CPU: SafeRefCell<Cpu> = SafeRefCell::new(Cpu::new());
 
-const MAX_CORE_NUM: usize = 16;
+pub(crate) const MAX_CORE_NUM: usize = 16;
 
 static CPUS: [SafeRefCell<Cpu>; MAX_CORE_NUM] = [DEFAULT_CPU; MAX_CORE_NUM];
 
@@ -128,6 +218,36 @@ pub fn mycpu() -> RefMut<'static, Cpu> {
     CPUS[cpu_id() as usize].0.borrow_mut()
 }
 
+// One reader slot per BrLock, allocated from a bitmap of this many bits.
+pub(crate) const MAX_BR_LOCKS: usize = usize::BITS as usize;
+
+// Per-cpu state that other cpus access as well, so it is made of atomics
+// instead of living in `Cpu`.
+#[repr(align(64))]
+pub(crate) struct SharedCpu {
+    pub(crate) br_readers: [AtomicUsize; MAX_BR_LOCKS], // Read depth of every BrLock on this cpu.
+    pub(crate) rcu_online: AtomicBool,                  // Must grace periods wait for this cpu?
+    pub(crate) rcu_qs: AtomicUsize,                     // Grace period of the last quiescent state.
+    pub(crate) epoch: AtomicUsize, // Pinned epoch << 1 | 1, or 0 if not pinned.
+}
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_BR_READERS: AtomicUsize = AtomicUsize::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_SHARED_CPU: SharedCpu = SharedCpu {
+    br_readers: [DEFAULT_BR_READERS; MAX_BR_LOCKS],
+    rcu_online: AtomicBool::new(false),
+    rcu_qs: AtomicUsize::new(0),
+    epoch: AtomicUsize::new(0),
+};
+
+static SHARED_CPUS: [SharedCpu; MAX_CORE_NUM] = [DEFAULT_SHARED_CPU; MAX_CORE_NUM];
+
+pub(crate) fn shared_cpu(id: usize) -> &'static SharedCpu {
+    &SHARED_CPUS[id]
+}
+
 // push_off/pop_off are like intr_off()/intr_on() except that they are matched:
 // it takes two pop_off()s to undo two push_off()s.  Also, if interrupts
 // are initially off, then push_off, pop_off leaves them off.
@@ -154,3 +274,64 @@ pub(crate) fn pop_off() {
         intr_on();
     }
 }
+
+// The kernel's hook applying a cpu priority level, 0 if none is registered.
+static PRIORITY_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Registers the hook that applies the current cpu's priority level, e.g. by
+/// programming the interrupt controller to mask the interrupts at or below it,
+/// or by telling the scheduler not to preempt below it.
+///
+/// The hook runs with interrupts off. Level 0 is the level of normal tasks.
+pub fn register_priority_hook(hook: fn(usize)) {
+    PRIORITY_HOOK.store(hook as usize, Ordering::Release);
+}
+
+pub(crate) fn priority_hook_registered() -> bool {
+    PRIORITY_HOOK.load(Ordering::Acquire) != 0
+}
+
+fn apply_priority(level: usize) {
+    let hook = PRIORITY_HOOK.load(Ordering::Acquire);
+    if hook != 0 {
+        // Safety: only `register_priority_hook` stores a non-zero value, a `fn(usize)`.
+        let hook: fn(usize) = unsafe { mem::transmute(hook) };
+        hook(level);
+    }
+}
+
+/// Returns the priority level of the current cpu.
+pub fn current_priority() -> usize {
+    push_off();
+    let level = mycpu().priority;
+    pop_off();
+    level
+}
+
+// push_priority/pop_priority are like push_off/pop_off for priority levels:
+// push_priority() raises the cpu to `level` and returns the level to hand
+// back to the matching pop_priority().
+pub(crate) fn push_priority(level: usize) -> usize {
+    push_off();
+    let mut cpu = mycpu();
+    let old = cpu.priority;
+    cpu.priority = level;
+    drop(cpu);
+    if old != level {
+        apply_priority(level);
+    }
+    pop_off();
+    old
+}
+
+pub(crate) fn pop_priority(old: usize) {
+    push_off();
+    let mut cpu = mycpu();
+    let level = cpu.priority;
+    cpu.priority = old;
+    drop(cpu);
+    if old != level {
+        apply_priority(old);
+    }
+    pop_off();
+}
@@ -1,23 +1,74 @@
+//! Locks and other synchronization primitives for kernels.
+//!
+//! # Signalling from interrupt handlers
+//!
+//! The async primitives, [`async_semaphore`], [`event`], [`notify`],
+//! [`oneshot`] and [`mpsc`], may be signalled from interrupt handlers:
+//! releasing, setting, notifying and sending without waiting neither block nor
+//! allocate. Only waiting allocates, when a future first queues itself, and a
+//! channel is allocated in full when it is created, then freed by whichever
+//! end is dropped last.
+//!
+//! Their state is under a [`SpinMutex`](spin::SpinMutex), which keeps
+//! interrupts off while it is held, so a handler never finds it held on its
+//! own cpu. Wakers are called with that lock held, so they must only schedule
+//! their task, not poll it.
+
 #![no_std]
 
+extern crate alloc;
+
+pub mod async_mutex;
+pub mod async_rwlock;
+pub mod async_semaphore;
+pub mod barrier;
+pub mod brlock;
+pub mod ceiling;
+pub mod cohort;
+pub mod condvar;
+pub mod epoch;
+pub mod event;
+pub mod fair_rwlock;
+pub mod futex;
+pub mod hazard;
+mod interrupt;
+pub mod mcslock;
+pub mod mpsc;
+pub mod notify;
+pub mod once;
+pub mod oneshot;
+pub mod rcu;
+pub mod rt_mutex;
+pub mod rwlock;
+pub mod sched;
+pub mod semaphore;
+pub mod seqlock;
+pub mod sleep_mutex;
+pub mod spin;
+pub mod srcu;
+pub mod ticket;
+pub mod topology;
+pub mod twa;
+pub mod wait_queue;
+mod waker_list;
+pub mod ww_mutex;
+
+pub use {
+    async_mutex::*, async_rwlock::*, async_semaphore::*, brlock::*, ceiling::*, cohort::*,
+    condvar::*, event::*, fair_rwlock::*, futex::*, hazard::*, notify::*, rcu::*, rt_mutex::*,
+    semaphore::*, seqlock::*, srcu::*, twa::*, ww_mutex::*,
+};
+
 cfg_if::cfg_if! {
     if #[cfg(all(target_os = "none", feature = "ticket"))] {
-        extern crate alloc;
-        mod interrupt;
-        pub mod mcslock;
-        pub mod rwlock;
-        pub use {rwlock::*, mcslock::*};
-        pub mod ticket;
+        pub use {barrier::*, rwlock::*, mcslock::*, once::*};
         pub use ticket::{TicketMutex as Mutex, TicketMutexGuard as MutexGuard};
     } else if #[cfg(target_os = "none")] {
-        extern crate alloc;
-        mod interrupt;
-        pub mod mcslock;
-        pub mod rwlock;
-        pub use {rwlock::*, mcslock::*};
-        pub mod spin;
-        pub use spin::{SpinMutex as Mutex, SpinMutexGuard as MutexGuard};
+        pub use {barrier::*, rwlock::*, mcslock::*, once::*};
+        pub use self::spin::{SpinMutex as Mutex, SpinMutexGuard as MutexGuard};
     } else {
-        pub use spin::*;
+        // LibOS mode: the top-level names come from the `spin` crate, while the
+        // modules above run on the hosted cpu simulation in `interrupt.rs`.
+        pub use ::spin::*;
     }
 }
@@ -0,0 +1,365 @@
+//! A bounded multi-producer, single-consumer channel into an async task.
+//!
+//! [`Sender::try_send`] queues a value if the buffer has room and gives it
+//! back otherwise, while tasks may instead await [`Sender::send`], which waits
+//! for room. The [`Receiver`] awaits values with [`Receiver::recv`], which
+//! resolves to `None` once every sender is gone or the receiver is closed,
+//! and the buffer is drained.
+//!
+//! Senders waiting for room are served in arrival order: each slot the
+//! receiver frees is kept for the sender that has waited longest, so
+//! `try_send` only takes slots nobody waits for.
+//!
+//! Interrupt handlers may queue values with `try_send`; see
+//! [the crate docs](crate#signalling-from-interrupt-handlers).
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::{spin::SpinMutex, waker_list::WakerList};
+
+struct State<T> {
+    buffer: VecDeque<T>,
+    capacity: usize,
+    reserved: usize, // Slots kept for picked senders.
+    senders: usize,
+    closed: bool, // Set once the receiver is closed or dropped.
+    rx_waker: Option<Waker>,
+    send_waiters: WakerList,
+}
+
+impl<T> State<T> {
+    fn has_room(&self) -> bool {
+        self.buffer.len() + self.reserved < self.capacity
+    }
+
+    /// Keeps the free slots for the senders at the front.
+    fn grant(&mut self) {
+        while self.has_room() && self.send_waiters.wake_front() {
+            self.reserved += 1;
+        }
+    }
+
+    fn push(&mut self, value: T) {
+        self.buffer.push_back(value);
+        if let Some(waker) = self.rx_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Inner<T> {
+    state: SpinMutex<State<T>>,
+}
+
+/// The sending half of a bounded channel, cloned for each producer.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The receiving half of a bounded channel.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The future returned by [`Sender::send`].
+pub struct Send<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    id: Option<usize>, // Our place in the queue, once we have one.
+}
+
+/// The future returned by [`Receiver::recv`].
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+/// The error returned by [`Sender::send`] when the receiver is gone, giving
+/// the value back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+/// The error returned by [`Sender::try_send`], giving the value back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// The buffer has no room.
+    Full(T),
+    /// The receiver is gone.
+    Closed(T),
+}
+
+/// The error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The buffer is empty, but senders remain.
+    Empty,
+    /// The buffer is empty and every sender is gone, or the receiver is closed.
+    Closed,
+}
+
+/// Creates a channel buffering up to `capacity` values, returning its two
+/// halves.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc channel capacity must be non-zero");
+    let inner = Arc::new(Inner {
+        state: SpinMutex::new(State {
+            buffer: VecDeque::with_capacity(capacity),
+            capacity,
+            reserved: 0,
+            senders: 1,
+            closed: false,
+            rx_waker: None,
+            send_waiters: WakerList::new(),
+        }),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Queues `value` if there is room, without waiting.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.inner.state.lock();
+        if state.closed {
+            Err(TrySendError::Closed(value))
+        } else if !state.has_room() {
+            Err(TrySendError::Full(value))
+        } else {
+            state.push(value);
+            Ok(())
+        }
+    }
+
+    /// Returns a future that queues `value` once there is room.
+    pub fn send(&self, value: T) -> Send<'_, T> {
+        Send {
+            sender: self,
+            value: Some(value),
+            id: None,
+        }
+    }
+
+    /// Returns whether the receiver has been dropped or closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.lock().closed
+    }
+
+    /// Returns the number of values the buffer holds at most.
+    pub fn capacity(&self) -> usize {
+        self.inner.state.lock().capacity
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.inner.state.lock().senders += 1;
+        Sender {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    /// The dropping of the last Sender wakes the receiver, so it sees the end
+    /// of the channel once the buffer is drained.
+    fn drop(&mut self) {
+        let mut state = self.inner.state.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            if let Some(waker) = state.rx_waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+// The value is never pinned, only moved into the buffer.
+impl<'a, T> Unpin for Send<'a, T> {}
+
+impl<'a, T> Future for Send<'a, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut state = this.sender.inner.state.lock();
+        if state.closed {
+            // Slots kept for senders no longer matter.
+            if let Some(id) = this.id.take() {
+                state.send_waiters.remove(id);
+            }
+            let value = this.value.take().expect("Send polled after completion");
+            return Poll::Ready(Err(SendError(value)));
+        }
+        match this.id {
+            None if state.send_waiters.waiting() == 0 && state.has_room() => {}
+            None => {
+                this.id = Some(state.send_waiters.push((), cx.waker()));
+                return Poll::Pending;
+            }
+            // `grant` kept a slot for us when it picked us.
+            Some(id) if state.send_waiters.take_picked(id) => {
+                this.id = None;
+                state.reserved -= 1;
+            }
+            Some(id) => {
+                state.send_waiters.update(id, cx.waker());
+                return Poll::Pending;
+            }
+        }
+        let value = this.value.take().expect("Send polled after completion");
+        state.push(value);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a, T> Drop for Send<'a, T> {
+    /// Leaves the queue, passing on the slot kept for us if we were picked.
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.sender.inner.state.lock();
+            // `close` wakes senders without keeping slots for them.
+            if state.send_waiters.remove(id).is_some() && !state.closed {
+                state.reserved -= 1;
+                state.grant();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Returns a future that takes the next value, or `None` once every
+    /// sender is gone or the receiver is closed, and the buffer is drained.
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    /// Takes the next value if there is one, without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.inner.state.lock();
+        match state.buffer.pop_front() {
+            Some(value) => {
+                state.grant();
+                Ok(value)
+            }
+            None if state.senders == 0 || state.closed => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Keeps the senders from sending, while the values already queued can
+    /// still be received. Once they are, the channel has ended.
+    pub fn close(&mut self) {
+        let mut state = self.inner.state.lock();
+        state.closed = true;
+        state.send_waiters.wake_all();
+    }
+
+    /// Returns the number of values queued.
+    pub fn len(&self) -> usize {
+        self.inner.state.lock().buffer.len()
+    }
+
+    /// Returns whether no values are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, T> Future for Recv<'a, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.receiver.inner.state.lock();
+        if let Some(value) = state.buffer.pop_front() {
+            state.grant();
+            return Poll::Ready(Some(value));
+        }
+        if state.senders == 0 || state.closed {
+            return Poll::Ready(None);
+        }
+        match &mut state.rx_waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            waker => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    /// The dropping of the Receiver closes the channel and drops the values
+    /// still queued.
+    fn drop(&mut self) {
+        let buffer = {
+            let mut state = self.inner.state.lock();
+            state.closed = true;
+            state.rx_waker = None;
+            state.send_waiters.wake_all();
+            core::mem::take(&mut state.buffer)
+        };
+        // Outside the lock, as dropping them may run arbitrary code.
+        drop(buffer);
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.state.lock();
+        write!(
+            f,
+            "Sender {{ len: {}, capacity: {}, closed: {} }}",
+            state.buffer.len(),
+            state.capacity,
+            state.closed
+        )
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.state.lock();
+        write!(
+            f,
+            "Receiver {{ len: {}, capacity: {}, senders: {} }}",
+            state.buffer.len(),
+            state.capacity,
+            state.senders
+        )
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channel closed")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "channel full"),
+            TrySendError::Closed(_) => write!(f, "channel closed"),
+        }
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "channel empty"),
+            TryRecvError::Closed => write!(f, "channel closed"),
+        }
+    }
+}
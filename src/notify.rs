@@ -0,0 +1,151 @@
+//! Wakes async tasks waiting for something to happen, without data.
+//!
+//! [`Notify::notify_one`] wakes the task that has waited longest, or, if
+//! nobody waits, leaves a single permit that the next
+//! [`notified`](Notify::notified) future takes at once.
+//! [`Notify::notify_waiters`] wakes every `notified` future created before
+//! the call, polled or not, and leaves no permit.
+//!
+//! Interrupt handlers may notify; see [the crate docs](crate#signalling-from-interrupt-handlers).
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{spin::SpinMutex, waker_list::WakerList};
+
+struct State {
+    permit: bool,             // Left by `notify_one` with nobody waiting.
+    waiters: WakerList<bool>, // Each set once `notify_one` picks it.
+}
+
+impl State {
+    fn notify_one(&mut self) {
+        match self.waiters.front_mut() {
+            Some(by_one) => {
+                *by_one = true;
+                self.waiters.wake_front();
+            }
+            None => self.permit = true,
+        }
+    }
+}
+
+pub struct Notify {
+    generation: AtomicUsize, // Bumped by `notify_waiters`, under the lock.
+    state: SpinMutex<State>,
+}
+
+/// The future returned by [`Notify::notified`].
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    generation: usize, // Of the `Notify`, when we were created.
+    id: Option<usize>, // Our place in the queue, once we have one.
+}
+
+impl Notify {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Notify {
+            generation: AtomicUsize::new(0),
+            state: SpinMutex::new(State {
+                permit: false,
+                waiters: WakerList::new(),
+            }),
+        }
+    }
+
+    /// Returns a future that resolves once we are notified.
+    pub fn notified(&self) -> Notified {
+        Notified {
+            notify: self,
+            generation: self.generation.load(Ordering::Acquire),
+            id: None,
+        }
+    }
+
+    /// Wakes the task that has waited longest, or leaves a permit for the
+    /// next one if nobody is waiting.
+    pub fn notify_one(&self) {
+        self.state.lock().notify_one();
+    }
+
+    /// Wakes every task waiting right now.
+    pub fn notify_waiters(&self) {
+        let mut state = self.state.lock();
+        self.generation.fetch_add(1, Ordering::Release);
+        state.waiters.wake_all();
+    }
+}
+
+impl<'a> Future for Notified<'a> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let notify = self.notify;
+        let mut state = notify.state.lock();
+        let notified = match self.id {
+            _ if notify.generation.load(Ordering::Relaxed) != self.generation => true,
+            None if state.permit => {
+                state.permit = false;
+                true
+            }
+            None => {
+                self.id = Some(state.waiters.push(false, cx.waker()));
+                false
+            }
+            Some(id) if state.waiters.take_picked(id) => {
+                self.id = None;
+                true
+            }
+            Some(id) => {
+                state.waiters.update(id, cx.waker());
+                false
+            }
+        };
+        if !notified {
+            return Poll::Pending;
+        }
+        if let Some(id) = self.id.take() {
+            // Woken by `notify_waiters`. A `notify_one` picking us as well is
+            // used up here.
+            state.waiters.remove(id);
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<'a> Drop for Notified<'a> {
+    /// Leaves the queue, passing a `notify_one` on if it picked us. A
+    /// `notify_waiters` wakeup is not, as it woke everyone waiting anyway.
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.notify.state.lock();
+            if state.waiters.remove(id) == Some(true) {
+                state.notify_one();
+            }
+        }
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Notify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock();
+        write!(
+            f,
+            "Notify {{ permit: {}, waiters: {} }}",
+            state.permit,
+            state.waiters.waiting()
+        )
+    }
+}
@@ -0,0 +1,408 @@
+//! One-time initialization: `Once`, `OnceCell` and `Lazy`.
+//!
+//! The first cpu to get there runs the initializer, the others spin until it
+//! is done. An interrupt handler that asks for the value while its own cpu is
+//! in the middle of initializing it would spin forever, so it panics instead.
+//! For the same reason, the initializer must not be preempted.
+//!
+//! If the initializer panics, the `Once` is poisoned and every later access
+//! panics too.
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    hint::spin_loop,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+
+use crate::interrupt::cpu_id;
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+const POISONED: u8 = 3;
+
+const NO_CPU: usize = usize::MAX;
+
+/// A value initialized once, by whichever cpu calls [`call_once`](Once::call_once) first.
+pub struct Once<T = ()> {
+    state: AtomicU8,
+    owner: AtomicUsize, // Cpu running the initializer.
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+
+// Poisons the `Once` if the initializer unwinds.
+struct Finish<'a> {
+    state: &'a AtomicU8,
+    owner: &'a AtomicUsize,
+}
+
+impl<'a> Drop for Finish<'a> {
+    fn drop(&mut self) {
+        self.owner.store(NO_CPU, Ordering::Relaxed);
+        self.state.store(POISONED, Ordering::Release);
+    }
+}
+
+impl<T> Once<T> {
+    /// Initialization constant of `Once`.
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const INIT: Self = Self::new();
+
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Once {
+            state: AtomicU8::new(INCOMPLETE),
+            owner: AtomicUsize::new(NO_CPU),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Creates a `Once` that is already initialized with `data`.
+    #[inline(always)]
+    pub const fn initialized(data: T) -> Self {
+        Once {
+            state: AtomicU8::new(COMPLETE),
+            owner: AtomicUsize::new(NO_CPU),
+            data: UnsafeCell::new(MaybeUninit::new(data)),
+        }
+    }
+
+    /// Runs `f` if no cpu has done so yet, waits for it otherwise, and
+    /// returns the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Once` is poisoned, or if the current cpu is already
+    /// running the initializer.
+    pub fn call_once<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self.try_call_once(|| Ok::<T, core::convert::Infallible>(f())) {
+            Ok(data) => data,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`call_once`](Once::call_once), but `f` may fail, in which case
+    /// the error is returned and the `Once` is left uninitialized.
+    pub fn try_call_once<F: FnOnce() -> Result<T, E>, E>(&self, f: F) -> Result<&T, E> {
+        loop {
+            match self.state.compare_exchange_weak(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return self.run(f),
+                Err(COMPLETE) => return Ok(unsafe { self.force_get() }),
+                Err(POISONED) => panic!("Once poisoned"),
+                Err(RUNNING) => {
+                    self.check_reentrant();
+                    if let Some(data) = self.poll_running() {
+                        return Ok(data);
+                    }
+                }
+                // Spurious failure of the weak exchange.
+                Err(_) => {}
+            }
+        }
+    }
+
+    fn run<F: FnOnce() -> Result<T, E>, E>(&self, f: F) -> Result<&T, E> {
+        self.owner.store(cpu_id() as usize, Ordering::Relaxed);
+        let finish = Finish {
+            state: &self.state,
+            owner: &self.owner,
+        };
+        let result = f();
+        core::mem::forget(finish);
+        self.owner.store(NO_CPU, Ordering::Relaxed);
+        match result {
+            Ok(data) => {
+                unsafe { (*self.data.get()).as_mut_ptr().write(data) };
+                self.state.store(COMPLETE, Ordering::Release);
+                Ok(unsafe { self.force_get() })
+            }
+            Err(err) => {
+                self.state.store(INCOMPLETE, Ordering::Release);
+                Err(err)
+            }
+        }
+    }
+
+    fn check_reentrant(&self) {
+        if self.owner.load(Ordering::Relaxed) == cpu_id() as usize {
+            panic!("re-entrant Once initialization on the same cpu");
+        }
+    }
+
+    // Waits while another cpu runs the initializer. Returns `None` if it failed.
+    fn poll_running(&self) -> Option<&T> {
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                RUNNING => spin_loop(),
+                COMPLETE => return Some(unsafe { self.force_get() }),
+                POISONED => panic!("Once poisoned"),
+                _ => return None,
+            }
+        }
+    }
+
+    /// Returns the value if it has been initialized.
+    #[inline(always)]
+    pub fn get(&self) -> Option<&T> {
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => Some(unsafe { self.force_get() }),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it has been initialized.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        match *self.state.get_mut() {
+            COMPLETE => Some(unsafe { (*self.data.get()).assume_init_mut() }),
+            _ => None,
+        }
+    }
+
+    /// Spins until the value has been initialized by another cpu.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `Once` is poisoned, or if the current cpu is running the initializer.
+    pub fn wait(&self) -> &T {
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                COMPLETE => return unsafe { self.force_get() },
+                POISONED => panic!("Once poisoned"),
+                RUNNING => self.check_reentrant(),
+                _ => {}
+            }
+            spin_loop();
+        }
+    }
+
+    /// Returns the value if it has been initialized, waiting for a cpu
+    /// running the initializer to finish.
+    pub fn poll(&self) -> Option<&T> {
+        match self.state.load(Ordering::Acquire) {
+            COMPLETE => Some(unsafe { self.force_get() }),
+            POISONED => panic!("Once poisoned"),
+            RUNNING => {
+                self.check_reentrant();
+                self.poll_running()
+            }
+            _ => None,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Returns `true` if an initializer panicked.
+    #[inline(always)]
+    pub fn is_poisoned(&self) -> bool {
+        self.state.load(Ordering::Acquire) == POISONED
+    }
+
+    /// Consumes the `Once`, returning the value if it has been initialized.
+    #[inline(always)]
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    fn take(&mut self) -> Option<T> {
+        match *self.state.get_mut() {
+            COMPLETE => {
+                *self.state.get_mut() = INCOMPLETE;
+                Some(unsafe { (*self.data.get()).as_ptr().read() })
+            }
+            _ => None,
+        }
+    }
+
+    // The state must be COMPLETE.
+    unsafe fn force_get(&self) -> &T {
+        &*(*self.data.get()).as_ptr()
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for Once<T> {
+    fn from(data: T) -> Self {
+        Self::initialized(data)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Once<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(data) => write!(f, "Once {{ data: ")
+                .and_then(|()| data.fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "Once {{ <uninitialized> }}"),
+        }
+    }
+}
+
+/// A cell written at most once.
+pub struct OnceCell<T> {
+    once: Once<T>,
+}
+
+impl<T> OnceCell<T> {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        OnceCell { once: Once::new() }
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> Option<&T> {
+        self.once.get()
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.once.get_mut()
+    }
+
+    /// Sets the value, or gives it back if the cell was already set.
+    pub fn set(&self, data: T) -> Result<(), T> {
+        let mut data = Some(data);
+        self.once.call_once(|| data.take().unwrap());
+        match data {
+            None => Ok(()),
+            Some(data) => Err(data),
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty.
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.once.call_once(f)
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty and
+    /// leaving it empty if `f` fails.
+    pub fn get_or_try_init<F: FnOnce() -> Result<T, E>, E>(&self, f: F) -> Result<&T, E> {
+        self.once.try_call_once(f)
+    }
+
+    /// Returns `true` if an initializer panicked.
+    #[inline(always)]
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+
+    #[inline(always)]
+    pub fn take(&mut self) -> Option<T> {
+        self.once.take()
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> Option<T> {
+        self.once.into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    fn from(data: T) -> Self {
+        OnceCell {
+            once: Once::initialized(data),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(data) => write!(f, "OnceCell {{ data: ")
+                .and_then(|()| data.fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "OnceCell {{ <uninitialized> }}"),
+        }
+    }
+}
+
+/// A value initialized on first access.
+pub struct Lazy<T, F = fn() -> T> {
+    once: Once<T>,
+    init: Cell<Option<F>>,
+}
+
+// The initializer is only taken by the cpu that won the `Once`.
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F> Lazy<T, F> {
+    #[inline(always)]
+    pub const fn new(init: F) -> Self {
+        Lazy {
+            once: Once::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// Returns `true` if an initializer panicked.
+    #[inline(always)]
+    pub fn is_poisoned(&self) -> bool {
+        self.once.is_poisoned()
+    }
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Initializes the value if needed and returns it.
+    pub fn force(this: &Self) -> &T {
+        this.once.call_once(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("Lazy instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Self::force(self)
+    }
+}
+
+impl<T: Default> Default for Lazy<T> {
+    fn default() -> Self {
+        Self::new(T::default)
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.once.get() {
+            Some(data) => write!(f, "Lazy {{ data: ")
+                .and_then(|()| data.fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "Lazy {{ <uninitialized> }}"),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! A channel for sending a single value to an async task.
+//!
+//! The [`Receiver`] is a future the task awaits, which resolves to the value
+//! passed to [`Sender::send`], or to [`RecvError`] if the sender was dropped
+//! without sending.
+//!
+//! Interrupt handlers may send, to complete a request with its result; see
+//! [the crate docs](crate#signalling-from-interrupt-handlers).
+
+use alloc::sync::Arc;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use crate::spin::SpinMutex;
+
+struct State<T> {
+    value: Option<T>,
+    waker: Option<Waker>, // Of the receiving task, once it has been polled.
+    tx_dropped: bool,     // Set once the sender has sent or gone away.
+    rx_dropped: bool,
+}
+
+struct Inner<T> {
+    state: SpinMutex<State<T>>,
+}
+
+/// The sending half of a oneshot channel.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The receiving half of a oneshot channel, awaited for the value.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// The error a [`Receiver`] resolves to when its sender is dropped unsent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+/// The error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// Nothing has been sent yet.
+    Empty,
+    /// The sender was dropped without sending, or the value was taken.
+    Closed,
+}
+
+/// Creates a oneshot channel, returning its two halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        state: SpinMutex::new(State {
+            value: None,
+            waker: None,
+            tx_dropped: false,
+            rx_dropped: false,
+        }),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, waking the receiving task.
+    ///
+    /// Gives `value` back if the receiver is gone.
+    pub fn send(self, value: T) -> Result<(), T> {
+        let mut state = self.inner.state.lock();
+        if state.rx_dropped {
+            return Err(value);
+        }
+        state.value = Some(value);
+        state.tx_dropped = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Returns whether the receiver has been dropped or closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.lock().rx_dropped
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    /// The dropping of the Sender without sending wakes the receiver with
+    /// [`RecvError`].
+    fn drop(&mut self) {
+        let mut state = self.inner.state.lock();
+        if !state.tx_dropped {
+            state.tx_dropped = true;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Takes the value if it has been sent, without waiting.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.inner.state.lock();
+        match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.tx_dropped => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Keeps the sender from sending, while a value already sent can still
+    /// be received.
+    pub fn close(&mut self) {
+        self.inner.state.lock().rx_dropped = true;
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.inner.state.lock();
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Ok(value));
+        }
+        if state.tx_dropped {
+            return Poll::Ready(Err(RecvError));
+        }
+        match &mut state.waker {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            waker => *waker = Some(cx.waker().clone()),
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    /// The dropping of the Receiver makes later sends fail, and drops a value
+    /// sent but not received.
+    fn drop(&mut self) {
+        let value = {
+            let mut state = self.inner.state.lock();
+            state.rx_dropped = true;
+            state.waker = None;
+            state.value.take()
+        };
+        // Outside the lock, as dropping it may run arbitrary code.
+        drop(value);
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sender {{ closed: {} }}", self.is_closed())
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.inner.state.lock();
+        write!(
+            f,
+            "Receiver {{ sent: {}, closed: {} }}",
+            state.value.is_some(),
+            state.tx_dropped && state.value.is_none()
+        )
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sender dropped without sending")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "nothing sent yet"),
+            TryRecvError::Closed => write!(f, "channel closed"),
+        }
+    }
+}
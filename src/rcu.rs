@@ -0,0 +1,351 @@
+//! Read-Copy-Update with quiescent-state tracking.
+//!
+//! Readers only bump a per-cpu nesting counter, and must not sleep or be
+//! switched out inside a read-side critical section. Grace periods are
+//! numbered by a global counter. Every online cpu records the grace period it
+//! was in at its last quiescent state, which the kernel reports from the
+//! context switch path ([`rcu_quiescent_state`]) and the scheduler tick
+//! ([`rcu_check_quiescent_state`]). A grace period is over once every online
+//! cpu has gone through a quiescent state after it started.
+//!
+//! The kernel has to:
+//! - call [`rcu_cpu_online`] when a cpu comes up, and [`rcu_cpu_offline`] when
+//!   it goes down or enters the idle loop (and `rcu_cpu_online` when it leaves),
+//! - report quiescent states as above, and never switch out a task while
+//!   [`rcu_read_lock_held`] says it is inside a read-side critical section,
+//! - call [`rcu_process_callbacks`] now and then, e.g. from a softirq or a
+//!   kernel thread, to run the [`call_rcu`] callbacks whose grace period is over.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt,
+    hint::spin_loop,
+    marker::PhantomData,
+    mem, ptr,
+    sync::atomic::{compiler_fence, fence, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupt::{cpu_id, mycpu, pop_off, push_off, shared_cpu, MAX_CORE_NUM},
+    spin::SpinMutex,
+};
+
+// The latest grace period started.
+static GP_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+struct Callback {
+    gp: usize, // Grace period that must be over before running `func`.
+    func: Box<dyn FnOnce() + Send>,
+}
+
+static CALLBACKS: SpinMutex<Vec<Callback>> = SpinMutex::new(Vec::new());
+
+/// Is grace period `gp` over for a cpu whose last quiescent state was in `qs`?
+#[inline(always)]
+fn reached(qs: usize, gp: usize) -> bool {
+    qs.wrapping_sub(gp) as isize >= 0
+}
+
+/// Returns the latest grace period that is over.
+fn completed() -> usize {
+    let gp = GP_SEQ.load(Ordering::SeqCst);
+    (0..MAX_CORE_NUM)
+        .map(shared_cpu)
+        .filter(|cpu| cpu.rcu_online.load(Ordering::SeqCst))
+        .map(|cpu| cpu.rcu_qs.load(Ordering::SeqCst))
+        .fold(gp, |completed, qs| {
+            if reached(qs, completed) {
+                completed
+            } else {
+                qs
+            }
+        })
+}
+
+/// A read-side critical section, ended when the guard is dropped.
+///
+/// The guard is bound to the cpu it was created on.
+pub struct RcuReadGuard {
+    _not_send: PhantomData<*mut ()>,
+}
+
+/// Enters a read-side critical section.
+///
+/// Read-side critical sections nest, and may be entered from interrupt handlers.
+#[inline(always)]
+pub fn rcu_read_lock() -> RcuReadGuard {
+    push_off();
+    mycpu().rcu_nesting += 1;
+    pop_off();
+    // Keep the reads of the section after the increment, as seen from a tick on this cpu.
+    compiler_fence(Ordering::SeqCst);
+    RcuReadGuard {
+        _not_send: PhantomData,
+    }
+}
+
+/// Returns whether the current cpu is inside a read-side critical section.
+///
+/// A preemptive scheduler must not switch tasks while this holds.
+#[inline(always)]
+pub fn rcu_read_lock_held() -> bool {
+    push_off();
+    let nesting = mycpu().rcu_nesting;
+    pop_off();
+    nesting > 0
+}
+
+/// Leaves a read-side critical section.
+#[inline(always)]
+pub fn rcu_read_unlock(guard: RcuReadGuard) {
+    drop(guard);
+}
+
+impl Drop for RcuReadGuard {
+    fn drop(&mut self) {
+        compiler_fence(Ordering::SeqCst);
+        push_off();
+        let mut cpu = mycpu();
+        debug_assert!(cpu.rcu_nesting > 0);
+        cpu.rcu_nesting -= 1;
+        drop(cpu);
+        pop_off();
+    }
+}
+
+/// Reports a quiescent state of the current cpu.
+///
+/// To be called on context switch, and more generally wherever the current
+/// cpu is known not to be in a read-side critical section.
+///
+/// # Panics
+///
+/// Panics inside a read-side critical section, whose reads a grace period
+/// ended by this would no longer protect.
+pub fn rcu_quiescent_state() {
+    assert!(
+        !rcu_read_lock_held(),
+        "quiescent state inside rcu_read_lock"
+    );
+    push_off();
+    report_quiescent_state();
+    pop_off();
+}
+
+/// Reports a quiescent state of the current cpu unless it was interrupted in a
+/// read-side critical section.
+///
+/// To be called from the scheduler tick.
+pub fn rcu_check_quiescent_state() {
+    push_off();
+    let nesting = mycpu().rcu_nesting;
+    if nesting == 0 {
+        report_quiescent_state();
+    }
+    pop_off();
+}
+
+// Must run with interrupts off.
+fn report_quiescent_state() {
+    // Order the accesses of the previous read-side critical sections before the report.
+    fence(Ordering::SeqCst);
+    let gp = GP_SEQ.load(Ordering::SeqCst);
+    shared_cpu(cpu_id() as usize)
+        .rcu_qs
+        .store(gp, Ordering::SeqCst);
+}
+
+/// Makes grace periods wait for the current cpu from now on.
+pub fn rcu_cpu_online() {
+    push_off();
+    // Report first, so that grace periods never see us online with a
+    // quiescent state from before we went offline.
+    report_quiescent_state();
+    shared_cpu(cpu_id() as usize)
+        .rcu_online
+        .store(true, Ordering::SeqCst);
+    pop_off();
+}
+
+/// Stops grace periods from waiting for the current cpu, which must not enter
+/// read-side critical sections until [`rcu_cpu_online`] is called again.
+pub fn rcu_cpu_offline() {
+    push_off();
+    debug_assert_eq!(mycpu().rcu_nesting, 0, "going offline inside rcu_read_lock");
+    fence(Ordering::SeqCst);
+    shared_cpu(cpu_id() as usize)
+        .rcu_online
+        .store(false, Ordering::SeqCst);
+    pop_off();
+}
+
+/// Starts a new grace period, returning its number.
+fn start_grace_period() -> usize {
+    GP_SEQ.fetch_add(1, Ordering::SeqCst).wrapping_add(1)
+}
+
+/// Waits until all read-side critical sections that were running when it was
+/// called have ended.
+///
+/// Must not be called inside a read-side critical section.
+pub fn synchronize_rcu() {
+    let gp = start_grace_period();
+    push_off();
+    let me = cpu_id() as usize;
+    if shared_cpu(me).rcu_online.load(Ordering::Relaxed) {
+        debug_assert_eq!(
+            mycpu().rcu_nesting,
+            0,
+            "synchronize_rcu inside rcu_read_lock"
+        );
+        report_quiescent_state();
+    }
+    pop_off();
+    for cpu in (0..MAX_CORE_NUM).filter(|cpu| *cpu != me).map(shared_cpu) {
+        while cpu.rcu_online.load(Ordering::SeqCst)
+            && !reached(cpu.rcu_qs.load(Ordering::SeqCst), gp)
+        {
+            spin_loop();
+        }
+    }
+    fence(Ordering::SeqCst);
+}
+
+/// Queues `func` to run once all current read-side critical sections have ended.
+///
+/// `func` is run by a later [`rcu_process_callbacks`].
+pub fn call_rcu<F: FnOnce() + Send + 'static>(func: F) {
+    let gp = start_grace_period();
+    CALLBACKS.lock().push(Callback {
+        gp,
+        func: Box::new(func),
+    });
+}
+
+/// Runs the callbacks queued by [`call_rcu`] whose grace period is over.
+pub fn rcu_process_callbacks() {
+    let completed = completed();
+    let ready: Vec<Callback> = {
+        let mut callbacks = CALLBACKS.lock();
+        let (ready, pending) = mem::take(&mut *callbacks)
+            .into_iter()
+            .partition(|callback| reached(completed, callback.gp));
+        *callbacks = pending;
+        ready
+    };
+    // Run them without holding the lock, they may call `call_rcu` themselves.
+    for callback in ready {
+        (callback.func)();
+    }
+}
+
+/// Waits for a grace period and then runs every callback queued so far.
+pub fn rcu_barrier() {
+    synchronize_rcu();
+    rcu_process_callbacks();
+}
+
+/// A pointer to RCU-protected data, owning what it points to.
+pub struct RcuPointer<T> {
+    ptr: AtomicPtr<T>,
+}
+
+// Raw pointer to an unpublished value, handed to `call_rcu`.
+struct Retired<T>(*mut T);
+
+// #Safety: No reader can reach it once its grace period is over.
+unsafe impl<T: Send> Send for Retired<T> {}
+
+impl<T> Retired<T> {
+    fn free(self) {
+        if !self.0.is_null() {
+            drop(unsafe { Box::from_raw(self.0) });
+        }
+    }
+}
+
+unsafe impl<T: Send + Sync> Sync for RcuPointer<T> {}
+unsafe impl<T: Send> Send for RcuPointer<T> {}
+
+impl<T> RcuPointer<T> {
+    /// Creates a pointer to nothing.
+    #[inline(always)]
+    pub const fn null() -> Self {
+        RcuPointer {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    #[inline(always)]
+    pub fn new(data: T) -> Self {
+        RcuPointer {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(data))),
+        }
+    }
+
+    /// Returns the data currently published, valid until the read-side
+    /// critical section of `guard` ends.
+    #[inline(always)]
+    pub fn dereference<'a>(&'a self, _guard: &'a RcuReadGuard) -> Option<&'a T> {
+        // Acquire pairs with the release in `publish`, so the data is seen fully initialized.
+        let ptr = self.ptr.load(Ordering::Acquire);
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Returns the data without a read-side critical section.
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        // We know statically that there are no readers.
+        unsafe { self.ptr.get_mut().as_mut() }
+    }
+
+    #[inline(always)]
+    pub fn is_null(&self) -> bool {
+        self.ptr.load(Ordering::Relaxed).is_null()
+    }
+}
+
+impl<T: Send + 'static> RcuPointer<T> {
+    /// Publishes `data` to new readers, and frees the previous data through
+    /// [`call_rcu`] once no reader can see it anymore.
+    pub fn publish(&self, data: T) {
+        self.swap(Box::into_raw(Box::new(data)));
+    }
+
+    /// Unpublishes the data, freeing it through [`call_rcu`].
+    pub fn clear(&self) {
+        self.swap(ptr::null_mut());
+    }
+
+    fn swap(&self, new: *mut T) {
+        let old = Retired(self.ptr.swap(new, Ordering::AcqRel));
+        if !old.0.is_null() {
+            call_rcu(move || old.free());
+        }
+    }
+}
+
+impl<T> Drop for RcuPointer<T> {
+    fn drop(&mut self) {
+        // Readers borrow `self`, so none can be left.
+        Retired(*self.ptr.get_mut()).free();
+    }
+}
+
+impl<T> Default for RcuPointer<T> {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RcuPointer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let guard = rcu_read_lock();
+        match self.dereference(&guard) {
+            Some(data) => write!(f, "RcuPointer {{ data: ")
+                .and_then(|()| data.fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RcuPointer {{ <null> }}"),
+        }
+    }
+}
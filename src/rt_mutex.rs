@@ -0,0 +1,366 @@
+//! A sleeping mutex with priority inheritance.
+//!
+//! A task blocked on an `RtMutex` lends its priority to the owner, and on
+//! through the chain of locks the owner is itself blocked on, so a
+//! low-priority owner cannot hold up a more urgent task for long. Unlocking
+//! hands the lock to the most urgent waiter and drops the owner back to the
+//! highest priority it still inherits.
+//!
+//! All the blocking relations live in one table under a single spin lock,
+//! which keeps the chain walks simple and consistent. Walking the chain also
+//! finds cycles, which [`RtMutex::lock_checked`] reports instead of deadlocking.
+
+use alloc::vec::Vec;
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{sched::PriorityScheduler, spin::SpinMutex};
+
+const NO_TASK: usize = usize::MAX;
+
+/// The part of an `RtMutex` the chain walks look at.
+struct RawRtMutex {
+    owner: AtomicUsize,
+    waiters: UnsafeCell<Vec<usize>>, // Under `PI`, in arrival order.
+}
+
+/// A task that holds or waits for an `RtMutex`.
+struct PiTask {
+    task: usize,
+    priority: usize,               // Effective priority.
+    blocked_on: *const RawRtMutex, // Null if not waiting.
+    wait_key: usize,               // What the task sleeps on while waiting.
+    held: Vec<*const RawRtMutex>,
+}
+
+struct PiState {
+    tasks: Vec<PiTask>,
+}
+
+// #Safety: The pointers are only followed under `PI`, while the locks are held or waited for.
+unsafe impl Send for PiState {}
+
+static PI: SpinMutex<PiState> = SpinMutex::new(PiState { tasks: Vec::new() });
+
+impl PiState {
+    fn find(&self, task: usize) -> Option<usize> {
+        self.tasks.iter().position(|entry| entry.task == task)
+    }
+
+    fn entry<S: PriorityScheduler>(&mut self, task: usize) -> &mut PiTask {
+        let index = match self.find(task) {
+            Some(index) => index,
+            None => {
+                self.tasks.push(PiTask {
+                    task,
+                    priority: S::base_priority(task),
+                    blocked_on: ptr::null(),
+                    wait_key: 0,
+                    held: Vec::new(),
+                });
+                self.tasks.len() - 1
+            }
+        };
+        &mut self.tasks[index]
+    }
+
+    fn priority<S: PriorityScheduler>(&self, task: usize) -> usize {
+        match self.find(task) {
+            Some(index) => self.tasks[index].priority,
+            None => S::base_priority(task),
+        }
+    }
+
+    /// The priority `task` should run at: its own, or that of its most urgent waiter.
+    fn inherited<S: PriorityScheduler>(&self, task: usize) -> usize {
+        let entry = &self.tasks[self.find(task).unwrap()];
+        entry
+            .held
+            .iter()
+            .flat_map(|lock| unsafe { (*(**lock).waiters.get()).iter() })
+            .map(|waiter| self.priority::<S>(*waiter))
+            .fold(S::base_priority(task), usize::max)
+    }
+
+    /// Recomputes the priority of `task`, and of the owners down its blocking chain.
+    fn propagate<S: PriorityScheduler>(&mut self, mut task: usize) {
+        // A cycle would loop forever, but `lock_checked` refuses to close one.
+        loop {
+            let priority = self.inherited::<S>(task);
+            let entry = self.entry::<S>(task);
+            if entry.priority == priority {
+                return;
+            }
+            entry.priority = priority;
+            S::set_priority(task, priority);
+            if entry.blocked_on.is_null() {
+                return;
+            }
+            task = unsafe { (*entry.blocked_on).owner.load(Ordering::Relaxed) };
+        }
+    }
+
+    /// Would `task` waiting for `lock` close a cycle?
+    fn would_deadlock(&self, task: usize, lock: &RawRtMutex) -> bool {
+        let mut owner = lock.owner.load(Ordering::Relaxed);
+        // Every task appears at most once on an acyclic chain.
+        for _ in 0..=self.tasks.len() {
+            if owner == task {
+                return true;
+            }
+            match self.find(owner) {
+                Some(index) if !self.tasks[index].blocked_on.is_null() => {
+                    owner = unsafe {
+                        (*self.tasks[index].blocked_on)
+                            .owner
+                            .load(Ordering::Relaxed)
+                    };
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Forgets `task` once nothing ties it to an `RtMutex` anymore.
+    fn release(&mut self, task: usize) {
+        if let Some(index) = self.find(task) {
+            let entry = &self.tasks[index];
+            if entry.held.is_empty() && entry.blocked_on.is_null() {
+                self.tasks.swap_remove(index);
+            }
+        }
+    }
+}
+
+/// The error returned when taking a lock would deadlock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadlock;
+
+impl fmt::Display for Deadlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RtMutex deadlock")
+    }
+}
+
+pub struct RtMutex<T: ?Sized, S> {
+    phantom: PhantomData<S>,
+    raw: RawRtMutex,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of an rt mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be handed to the most urgent waiter, or unlocked.
+///
+pub struct RtMutexGuard<'a, T: ?Sized + 'a, S: PriorityScheduler> {
+    mutex: &'a RtMutex<T, S>,
+    data: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send, S> Sync for RtMutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Send for RtMutex<T, S> {}
+
+impl<T, S> RtMutex<T, S> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        RtMutex {
+            phantom: PhantomData,
+            raw: RawRtMutex {
+                owner: AtomicUsize::new(NO_TASK),
+                waiters: UnsafeCell::new(Vec::new()),
+            },
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized, S: PriorityScheduler> RtMutex<T, S> {
+    /// Locks the mutex, lending our priority to its owner while we wait.
+    ///
+    /// # Panics
+    ///
+    /// Panics if waiting would deadlock.
+    pub fn lock(&self) -> RtMutexGuard<T, S> {
+        match self.lock_checked() {
+            Ok(guard) => guard,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Like [`lock`](RtMutex::lock), but returns an error instead of waiting
+    /// if the chain of owners leads back to the current task.
+    pub fn lock_checked(&self) -> Result<RtMutexGuard<T, S>, Deadlock> {
+        let me = S::current_task();
+        let raw = &self.raw;
+        // We sleep on a key of our own, the address of this local, so that
+        // unlocking wakes just the new owner.
+        let wait_slot = 0u8;
+        let key = &wait_slot as *const u8 as usize;
+        {
+            let mut pi = PI.lock();
+            if raw.owner.load(Ordering::Relaxed) == NO_TASK {
+                raw.owner.store(me, Ordering::Relaxed);
+                pi.entry::<S>(me).held.push(raw);
+                return Ok(self.guard());
+            }
+            if pi.would_deadlock(me, raw) {
+                return Err(Deadlock);
+            }
+            unsafe { (*raw.waiters.get()).push(me) };
+            let entry = pi.entry::<S>(me);
+            entry.blocked_on = raw;
+            entry.wait_key = key;
+            let owner = raw.owner.load(Ordering::Relaxed);
+            pi.propagate::<S>(owner);
+        }
+        // `unlock` hands the lock over by making us the owner.
+        while raw.owner.load(Ordering::Acquire) != me {
+            S::block(key, &mut || raw.owner.load(Ordering::Acquire) != me);
+        }
+        Ok(self.guard())
+    }
+
+    pub fn try_lock(&self) -> Option<RtMutexGuard<T, S>> {
+        let me = S::current_task();
+        let mut pi = PI.lock();
+        if self.raw.owner.load(Ordering::Relaxed) != NO_TASK {
+            return None;
+        }
+        self.raw.owner.store(me, Ordering::Relaxed);
+        pi.entry::<S>(me).held.push(&self.raw);
+        Some(self.guard())
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.raw.owner.load(Ordering::Relaxed) != NO_TASK
+    }
+
+    fn guard(&self) -> RtMutexGuard<T, S> {
+        RtMutexGuard {
+            mutex: self,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    fn unlock(&self) {
+        let raw: *const RawRtMutex = &self.raw;
+        let mut pi = PI.lock();
+        let me = self.raw.owner.load(Ordering::Relaxed);
+        let entry = pi.entry::<S>(me);
+        entry.held.retain(|lock| *lock != raw);
+        let waiters = unsafe { &mut *self.raw.waiters.get() };
+        // The most urgent waiter, the earliest among equals.
+        let next = waiters.iter().enumerate().fold(
+            None,
+            |best: Option<(usize, usize)>, (index, waiter)| {
+                let priority = pi.priority::<S>(*waiter);
+                match best {
+                    Some((_, top)) if top >= priority => best,
+                    _ => Some((index, priority)),
+                }
+            },
+        );
+        let wake = match next {
+            Some((index, _)) => {
+                let next = waiters.remove(index);
+                let entry = pi.entry::<S>(next);
+                entry.blocked_on = ptr::null();
+                entry.held.push(raw);
+                let key = entry.wait_key;
+                self.raw.owner.store(next, Ordering::Release);
+                // The new owner inherits from the waiters left behind.
+                pi.propagate::<S>(next);
+                Some(key)
+            }
+            None => {
+                self.raw.owner.store(NO_TASK, Ordering::Release);
+                None
+            }
+        };
+        pi.propagate::<S>(me);
+        pi.release(me);
+        drop(pi);
+        // The new owner may be gone from its wait already, in which case the
+        // wakeup is spurious for whoever sleeps on the key now.
+        if let Some(key) = wake {
+            S::wake(key, 1);
+        }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, S: PriorityScheduler> fmt::Debug for RtMutex<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "RtMutex {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "RtMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default, S> Default for RtMutex<T, S> {
+    fn default() -> Self {
+        RtMutex::new(T::default())
+    }
+}
+
+impl<T, S> From<T> for RtMutex<T, S> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized, S: PriorityScheduler> Drop for RtMutexGuard<'a, T, S> {
+    /// The dropping of the RtMutexGuard hands the lock on and restores our priority.
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+impl<'a, T: ?Sized, S: PriorityScheduler> Deref for RtMutexGuard<'a, T, S> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized, S: PriorityScheduler> DerefMut for RtMutexGuard<'a, T, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug, S: PriorityScheduler> fmt::Debug for RtMutexGuard<'a, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display, S: PriorityScheduler> fmt::Display for RtMutexGuard<'a, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
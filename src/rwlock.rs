@@ -4,23 +4,65 @@ use core::{
     cell::UnsafeCell,
     fmt,
     hint::spin_loop,
-    // marker::PhantomData,
+    marker::PhantomData,
     mem,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicUsize, Ordering},
 };
 
+use crate::condvar::CondvarGuard;
+#[cfg(debug_assertions)]
+use crate::interrupt::mycpu;
 use crate::interrupt::{pop_off, push_off};
 
-pub struct RwLock<T: ?Sized> {
+pub struct RwLock<T: ?Sized, P = UpgraderPreferring> {
+    phantom: PhantomData<P>,
     lock: AtomicUsize,
     data: UnsafeCell<T>,
 }
 
-const READER: usize = 1 << 2;
+const READER: usize = 1 << 3;
+const PENDING_WRITER: usize = 1 << 2;
 const UPGRADED: usize = 1 << 1;
 const WRITER: usize = 1;
 
+/// Decides which of readers, upgraders and writers a [`RwLock`] favours.
+pub trait RwLockPolicy {
+    /// Lock bits that make `try_read` refuse new readers.
+    const READER_BLOCKERS: usize;
+    /// Whether a writer spinning in [`RwLock::write`] sets the pending-writer bit.
+    const MARK_PENDING_WRITER: bool;
+}
+
+/// New readers are only refused while a writer holds the lock.
+///
+/// Writers and upgrades may starve under a steady stream of readers.
+pub struct ReaderPreferring;
+
+/// New readers are refused while a writer or an upgradeable reader holds the lock.
+///
+/// This is the behavior of `spin::RwLock`, and the default.
+pub struct UpgraderPreferring;
+
+/// New readers are also refused while a writer is spinning in [`RwLock::write`],
+/// so a writer only waits for the readers already inside.
+pub struct WriterPreferring;
+
+impl RwLockPolicy for ReaderPreferring {
+    const READER_BLOCKERS: usize = WRITER;
+    const MARK_PENDING_WRITER: bool = false;
+}
+
+impl RwLockPolicy for UpgraderPreferring {
+    const READER_BLOCKERS: usize = WRITER | UPGRADED;
+    const MARK_PENDING_WRITER: bool = false;
+}
+
+impl RwLockPolicy for WriterPreferring {
+    const READER_BLOCKERS: usize = WRITER | UPGRADED | PENDING_WRITER;
+    const MARK_PENDING_WRITER: bool = true;
+}
+
 /// A guard that provides immutable data access.
 ///
 /// When the guard falls out of scope it will decrement the read count,
@@ -33,28 +75,28 @@ pub struct RwLockReadGuard<'a, T: 'a + ?Sized> {
 /// A guard that provides mutable data access.
 ///
 /// When the guard falls out of scope it will release the lock.
-pub struct RwLockWriteGuard<'a, T: 'a + ?Sized> {
+pub struct RwLockWriteGuard<'a, T: 'a + ?Sized, P = UpgraderPreferring> {
     // phantom: PhantomData<R>,
-    inner: &'a RwLock<T>,
+    inner: &'a RwLock<T, P>,
     data: &'a mut T,
 }
 
 /// A guard that provides immutable data access but can be upgraded to [`RwLockWriteGuard`].
 ///
-/// No writers or other upgradeable guards can exist while this is in scope. New reader
-/// creation is prevented (to alleviate writer starvation) but there may be existing readers
-/// when the lock is acquired.
+/// No writers or other upgradeable guards can exist while this is in scope. Unless the lock is
+/// [`ReaderPreferring`], new reader creation is prevented (to alleviate writer starvation) but
+/// there may be existing readers when the lock is acquired.
 ///
 /// When the guard falls out of scope it will release the lock.
-pub struct RwLockUpgradableGuard<'a, T: 'a + ?Sized> {
+pub struct RwLockUpgradableGuard<'a, T: 'a + ?Sized, P = UpgraderPreferring> {
     // phantom: PhantomData<R>,
-    inner: &'a RwLock<T>,
+    inner: &'a RwLock<T, P>,
     data: &'a T,
 }
 
 // Same unsafe impls as `std::sync::RwLock`
-unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
-unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+unsafe impl<T: ?Sized + Send, P> Send for RwLock<T, P> {}
+unsafe impl<T: ?Sized + Send + Sync, P> Sync for RwLock<T, P> {}
 
 impl<T> RwLock<T> {
     /// Creates a new spinlock wrapping the supplied data.
@@ -74,8 +116,22 @@ impl<T> RwLock<T> {
     /// ```
     #[inline]
     pub const fn new(data: T) -> Self {
+        Self::with_policy(data)
+    }
+}
+
+impl<T, P> RwLock<T, P> {
+    /// Creates a new spinlock wrapping the supplied data, with the policy `P`.
+    ///
+    /// ```
+    /// use lock::rwlock::{RwLock, WriterPreferring};
+    ///
+    /// static RW_LOCK: RwLock<(), WriterPreferring> = RwLock::with_policy(());
+    /// ```
+    #[inline]
+    pub const fn with_policy(data: T) -> Self {
         RwLock {
-            // phantom: PhantomData,
+            phantom: PhantomData,
             lock: AtomicUsize::new(0),
             data: UnsafeCell::new(data),
         }
@@ -119,7 +175,7 @@ impl<T> RwLock<T> {
     }
 }
 
-impl<T: ?Sized> RwLock<T> {
+impl<T: ?Sized, P: RwLockPolicy> RwLock<T, P> {
     /// Locks this rwlock with shared read access, blocking the current thread
     /// until it can be acquired.
     ///
@@ -132,6 +188,9 @@ impl<T: ?Sized> RwLock<T> {
     /// Returns an RAII guard which will release this thread's shared access
     /// once it is dropped.
     ///
+    /// Unless the policy is [`ReaderPreferring`], a cpu that already holds a read guard of this
+    /// lock must use [`read_recursive`](RwLock::read_recursive) instead, debug builds panic otherwise.
+    ///
     /// ```
     /// let mylock = spin::RwLock::new(0);
     /// {
@@ -143,6 +202,10 @@ impl<T: ?Sized> RwLock<T> {
     /// ```
     #[inline]
     pub fn read(&self) -> RwLockReadGuard<T> {
+        // Readers refused only by a writer never wait on a nested read.
+        if P::READER_BLOCKERS != WRITER {
+            check_nested_read(&self.lock);
+        }
         loop {
             match self.try_read() {
                 Some(guard) => return guard,
@@ -170,11 +233,17 @@ impl<T: ?Sized> RwLock<T> {
     /// }
     /// ```
     #[inline]
-    pub fn write(&self) -> RwLockWriteGuard<T> {
+    pub fn write(&self) -> RwLockWriteGuard<T, P> {
         loop {
             match self.try_write_internal(false) {
                 Some(guard) => return guard,
-                None => spin_loop(),
+                None => {
+                    if P::MARK_PENDING_WRITER {
+                        // Keep new readers out while we wait for the current ones.
+                        self.lock.fetch_or(PENDING_WRITER, Ordering::Relaxed);
+                    }
+                    spin_loop()
+                }
             }
         }
     }
@@ -182,7 +251,7 @@ impl<T: ?Sized> RwLock<T> {
     /// Obtain a readable lock guard that can later be upgraded to a writable lock guard.
     /// Upgrades can be done through the [`RwLockUpgradableGuard::upgrade`](RwLockUpgradableGuard::upgrade) method.
     #[inline]
-    pub fn upgradeable_read(&self) -> RwLockUpgradableGuard<T> {
+    pub fn upgradeable_read(&self) -> RwLockUpgradableGuard<T, P> {
         loop {
             match self.try_upgradeable_read() {
                 Some(guard) => return guard,
@@ -218,14 +287,61 @@ impl<T: ?Sized> RwLock<T> {
         push_off();
         let value = self.lock.fetch_add(READER, Ordering::Acquire);
 
-        // We check the UPGRADED bit here so that new readers are prevented when an UPGRADED lock is held.
+        // Depending on the policy, we also check the UPGRADED and PENDING_WRITER bits here so that new
+        // readers are prevented when an UPGRADED lock is held or a writer is waiting.
         // This helps reduce writer starvation.
-        if value & (WRITER | UPGRADED) != 0 {
+        if value & P::READER_BLOCKERS != 0 {
             // Lock is taken, undo.
             self.lock.fetch_sub(READER, Ordering::Release);
             pop_off();
             None
         } else {
+            track_read(&self.lock);
+            Some(RwLockReadGuard {
+                lock: &self.lock,
+                data: unsafe { &*self.data.get() },
+            })
+        }
+    }
+
+    /// Locks this rwlock with shared read access, even if this cpu already holds it for reading.
+    ///
+    /// Unlike [`read`](RwLock::read), this only waits while a writer actually holds the lock, and
+    /// ignores upgradeable readers and waiting writers, like Linux's `read_lock` on `rwlock_t` in
+    /// interrupt context. A cpu that holds a read guard and re-enters `read` deadlocks against another
+    /// cpu spinning in [`upgrade`](RwLockUpgradableGuard::upgrade), while `read_recursive` does not.
+    ///
+    /// ```
+    /// let mylock = lock::rwlock::RwLock::new(0);
+    /// let outer = mylock.read();
+    /// let inner = mylock.read_recursive();
+    /// assert_eq!(*outer, *inner);
+    /// ```
+    #[inline]
+    pub fn read_recursive(&self) -> RwLockReadGuard<T> {
+        loop {
+            match self.try_read_recursive() {
+                Some(guard) => return guard,
+                None => spin_loop(),
+            }
+        }
+    }
+
+    /// Attempt to acquire this lock with shared read access, even if this cpu already holds it for reading.
+    ///
+    /// Returns `None` only if a writer holds the lock.
+    #[inline]
+    pub fn try_read_recursive(&self) -> Option<RwLockReadGuard<T>> {
+        push_off();
+        let value = self.lock.fetch_add(READER, Ordering::Acquire);
+
+        if value & WRITER != 0 {
+            // Lock is taken, undo.
+            self.lock.fetch_sub(READER, Ordering::Release);
+            pop_off();
+            None
+        } else {
+            track_read(&self.lock);
             Some(RwLockReadGuard {
                 lock: &self.lock,
                 data: unsafe { &*self.data.get() },
@@ -266,7 +382,7 @@ impl<T: ?Sized> RwLock<T> {
     /// RAII. The underlying atomic operation uses `Ordering::Release`.
     #[inline]
     pub unsafe fn force_read_decrement(&self) {
-        debug_assert!(self.lock.load(Ordering::Relaxed) & !WRITER > 0);
+        debug_assert!(self.lock.load(Ordering::Relaxed) >= READER);
         self.lock.fetch_sub(READER, Ordering::Release);
     }
 
@@ -280,16 +396,21 @@ impl<T: ?Sized> RwLock<T> {
     /// underlying atomic operation uses `Ordering::Release`.
     #[inline]
     pub unsafe fn force_write_unlock(&self) {
-        debug_assert_eq!(self.lock.load(Ordering::Relaxed) & !(WRITER | UPGRADED), 0);
+        debug_assert_eq!(
+            self.lock.load(Ordering::Relaxed) & !(WRITER | UPGRADED | PENDING_WRITER),
+            0
+        );
         self.lock.fetch_and(!(WRITER | UPGRADED), Ordering::Release);
     }
 
     #[inline(always)]
-    fn try_write_internal(&self, strong: bool) -> Option<RwLockWriteGuard<T>> {
+    fn try_write_internal(&self, strong: bool) -> Option<RwLockWriteGuard<T, P>> {
         push_off();
+        // Taking the lock clears the PENDING_WRITER bit, other waiting writers set it again.
+        let pending = self.lock.load(Ordering::Relaxed) & PENDING_WRITER;
         if compare_exchange(
             &self.lock,
-            0,
+            pending,
             WRITER,
             Ordering::Acquire,
             Ordering::Relaxed,
@@ -328,13 +449,13 @@ impl<T: ?Sized> RwLock<T> {
     /// }
     /// ```
     #[inline]
-    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T, P>> {
         self.try_write_internal(true)
     }
 
     /// Tries to obtain an upgradeable lock guard.
     #[inline]
-    pub fn try_upgradeable_read(&self) -> Option<RwLockUpgradableGuard<T>> {
+    pub fn try_upgradeable_read(&self) -> Option<RwLockUpgradableGuard<T, P>> {
         push_off();
         if self.lock.fetch_or(UPGRADED, Ordering::Acquire) & (WRITER | UPGRADED) == 0 {
             Some(RwLockUpgradableGuard {
@@ -369,7 +490,7 @@ impl<T: ?Sized> RwLock<T> {
     }
 }
 
-impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+impl<T: ?Sized + fmt::Debug, P: RwLockPolicy> fmt::Debug for RwLock<T, P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.try_read() {
             Some(guard) => write!(f, "RwLock {{ data: ")
@@ -380,15 +501,15 @@ impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
     }
 }
 
-impl<T: ?Sized + Default> Default for RwLock<T> {
+impl<T: ?Sized + Default, P> Default for RwLock<T, P> {
     fn default() -> Self {
-        Self::new(Default::default())
+        Self::with_policy(Default::default())
     }
 }
 
-impl<T> From<T> for RwLock<T> {
+impl<T, P> From<T> for RwLock<T, P> {
     fn from(data: T) -> Self {
-        Self::new(data)
+        Self::with_policy(data)
     }
 }
 
@@ -424,7 +545,7 @@ impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for RwLockReadGuard<'rwlock
     }
 }
 
-impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P: RwLockPolicy> RwLockUpgradableGuard<'rwlock, T, P> {
     /// Upgrades an upgradeable lock guard to a writable lock guard.
     ///
     /// ```
@@ -434,7 +555,7 @@ impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
     /// let writable = upgradeable.upgrade();
     /// ```
     #[inline]
-    pub fn upgrade(mut self) -> RwLockWriteGuard<'rwlock, T> {
+    pub fn upgrade(mut self) -> RwLockWriteGuard<'rwlock, T, P> {
         loop {
             self = match self.try_upgrade_internal(false) {
                 Ok(guard) => return guard,
@@ -446,12 +567,14 @@ impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P: RwLockPolicy> RwLockUpgradableGuard<'rwlock, T, P> {
     #[inline(always)]
-    fn try_upgrade_internal(self, strong: bool) -> Result<RwLockWriteGuard<'rwlock, T>, Self> {
+    fn try_upgrade_internal(self, strong: bool) -> Result<RwLockWriteGuard<'rwlock, T, P>, Self> {
+        // A waiting writer may have set PENDING_WRITER, it must not stop the upgrade.
+        let pending = self.inner.lock.load(Ordering::Relaxed) & PENDING_WRITER;
         if compare_exchange(
             &self.inner.lock,
-            UPGRADED,
+            UPGRADED | pending,
             WRITER,
             Ordering::Acquire,
             Ordering::Relaxed,
@@ -487,7 +610,7 @@ impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
     /// };
     /// ```
     #[inline]
-    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'rwlock, T>, Self> {
+    pub fn try_upgrade(self) -> Result<RwLockWriteGuard<'rwlock, T, P>, Self> {
         self.try_upgrade_internal(true)
     }
 
@@ -508,6 +631,7 @@ impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
     pub fn downgrade(self) -> RwLockReadGuard<'rwlock, T> {
         // Reserve the read guard for ourselves
         self.inner.lock.fetch_add(READER, Ordering::Acquire);
+        track_read(&self.inner.lock);
 
         let inner = self.inner;
 
@@ -538,19 +662,19 @@ impl<'rwlock, T: ?Sized> RwLockUpgradableGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for RwLockUpgradableGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized + fmt::Debug, P> fmt::Debug for RwLockUpgradableGuard<'rwlock, T, P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(&**self, f)
     }
 }
 
-impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for RwLockUpgradableGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized + fmt::Display, P> fmt::Display for RwLockUpgradableGuard<'rwlock, T, P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&**self, f)
     }
 }
 
-impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P: RwLockPolicy> RwLockWriteGuard<'rwlock, T, P> {
     /// Downgrades the writable lock guard to a readable, shared lock guard. Cannot fail and is guaranteed not to spin.
     ///
     /// ```
@@ -567,6 +691,7 @@ impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
     pub fn downgrade(self) -> RwLockReadGuard<'rwlock, T> {
         // Reserve the read guard for ourselves
         self.inner.lock.fetch_add(READER, Ordering::Acquire);
+        track_read(&self.inner.lock);
 
         let inner = self.inner;
 
@@ -591,14 +716,16 @@ impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
     /// assert_eq!(*readable, 1);
     /// ```
     #[inline]
-    pub fn downgrade_to_upgradeable(self) -> RwLockUpgradableGuard<'rwlock, T> {
+    pub fn downgrade_to_upgradeable(self) -> RwLockUpgradableGuard<'rwlock, T, P> {
         debug_assert_eq!(
             self.inner.lock.load(Ordering::Acquire) & (WRITER | UPGRADED),
             WRITER
         );
 
-        // Reserve the read guard for ourselves
-        self.inner.lock.store(UPGRADED, Ordering::Release);
+        // Swap WRITER for UPGRADED without a plain store, which would clear
+        // the PENDING_WRITER bit of a waiting writer.
+        self.inner.lock.fetch_or(UPGRADED, Ordering::Acquire);
+        self.inner.lock.fetch_and(!WRITER, Ordering::Release);
 
         let inner = self.inner;
 
@@ -633,13 +760,13 @@ impl<'rwlock, T: ?Sized> RwLockWriteGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized + fmt::Debug, P> fmt::Debug for RwLockWriteGuard<'rwlock, T, P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Debug::fmt(&**self, f)
     }
 }
 
-impl<'rwlock, T: ?Sized + fmt::Display> fmt::Display for RwLockWriteGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized + fmt::Display, P> fmt::Display for RwLockWriteGuard<'rwlock, T, P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(&**self, f)
     }
@@ -653,7 +780,7 @@ impl<'rwlock, T: ?Sized> Deref for RwLockReadGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized> Deref for RwLockUpgradableGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P> Deref for RwLockUpgradableGuard<'rwlock, T, P> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -661,7 +788,7 @@ impl<'rwlock, T: ?Sized> Deref for RwLockUpgradableGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized> Deref for RwLockWriteGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P> Deref for RwLockWriteGuard<'rwlock, T, P> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -669,7 +796,7 @@ impl<'rwlock, T: ?Sized> Deref for RwLockWriteGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized> DerefMut for RwLockWriteGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P> DerefMut for RwLockWriteGuard<'rwlock, T, P> {
     fn deref_mut(&mut self) -> &mut T {
         self.data
     }
@@ -677,13 +804,14 @@ impl<'rwlock, T: ?Sized> DerefMut for RwLockWriteGuard<'rwlock, T> {
 
 impl<'rwlock, T: ?Sized> Drop for RwLockReadGuard<'rwlock, T> {
     fn drop(&mut self) {
-        debug_assert!(self.lock.load(Ordering::Relaxed) & !(WRITER | UPGRADED) > 0);
+        debug_assert!(self.lock.load(Ordering::Relaxed) >= READER);
+        untrack_read(self.lock);
         self.lock.fetch_sub(READER, Ordering::Release);
         pop_off();
     }
 }
 
-impl<'rwlock, T: ?Sized> Drop for RwLockUpgradableGuard<'rwlock, T> {
+impl<'rwlock, T: ?Sized, P> Drop for RwLockUpgradableGuard<'rwlock, T, P> {
     fn drop(&mut self) {
         debug_assert_eq!(
             self.inner.lock.load(Ordering::Relaxed) & (WRITER | UPGRADED),
@@ -694,7 +822,21 @@ impl<'rwlock, T: ?Sized> Drop for RwLockUpgradableGuard<'rwlock, T> {
     }
 }
 
-impl<'rwlock, T: ?Sized> Drop for RwLockWriteGuard<'rwlock, T> {
+unsafe impl<'rwlock, T: ?Sized, P: RwLockPolicy> CondvarGuard for RwLockWriteGuard<'rwlock, T, P> {
+    unsafe fn unlock(&mut self) {
+        self.inner
+            .lock
+            .fetch_and(!(WRITER | UPGRADED), Ordering::Release);
+        pop_off();
+    }
+
+    unsafe fn relock(&mut self) {
+        // The new guard takes the lock for us, and we keep it through `self`.
+        mem::forget(self.inner.write());
+    }
+}
+
+impl<'rwlock, T: ?Sized, P> Drop for RwLockWriteGuard<'rwlock, T, P> {
     fn drop(&mut self) {
         debug_assert_eq!(self.inner.lock.load(Ordering::Relaxed) & WRITER, WRITER);
 
@@ -707,6 +849,48 @@ impl<'rwlock, T: ?Sized> Drop for RwLockWriteGuard<'rwlock, T> {
     }
 }
 
+// In debug builds every cpu keeps track of the locks it holds for reading, so that a nested `read`,
+// which can deadlock against an upgrade or a waiting writer, is caught. Tracking must run with
+// interrupts off.
+
+#[cfg(debug_assertions)]
+fn check_nested_read(lock: &AtomicUsize) {
+    let addr = lock as *const _ as usize;
+    push_off();
+    let nested = mycpu().held_reads.contains(&addr);
+    pop_off();
+    assert!(
+        !nested,
+        "nested RwLock::read on the same cpu, use read_recursive"
+    );
+}
+
+#[cfg(debug_assertions)]
+fn track_read(lock: &AtomicUsize) {
+    let addr = lock as *const _ as usize;
+    // Past the capacity of `held_reads`, further guards are just not checked.
+    if let Some(slot) = mycpu().held_reads.iter_mut().find(|slot| **slot == 0) {
+        *slot = addr;
+    }
+}
+
+#[cfg(debug_assertions)]
+fn untrack_read(lock: &AtomicUsize) {
+    let addr = lock as *const _ as usize;
+    if let Some(slot) = mycpu().held_reads.iter_mut().find(|slot| **slot == addr) {
+        *slot = 0;
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn check_nested_read(_lock: &AtomicUsize) {}
+
+#[cfg(not(debug_assertions))]
+fn track_read(_lock: &AtomicUsize) {}
+
+#[cfg(not(debug_assertions))]
+fn untrack_read(_lock: &AtomicUsize) {}
+
 #[inline(always)]
 fn compare_exchange(
     atomic: &AtomicUsize,
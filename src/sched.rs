@@ -0,0 +1,75 @@
+//! Hooks into the kernel scheduler for the primitives that can put a task to sleep.
+//!
+//! Sleeping is keyed by an address, usually that of the primitive's state
+//! word, in the style of futexes. The kernel implements [`Scheduler`] on a
+//! marker type and names it as a type parameter of the primitive; [`SpinWait`]
+//! spins instead, for atomic context and for kernels without a scheduler.
+
+use core::{hint::spin_loop, time::Duration};
+
+use crate::interrupt::cpu_id;
+
+/// The scheduler operations a sleeping primitive needs.
+pub trait Scheduler {
+    /// Returns an id of the current task, unique among the live tasks.
+    fn current_task() -> usize;
+
+    /// Puts the current task to sleep on `key`, unless `should_block` returns
+    /// `false`.
+    ///
+    /// `should_block` must be called with whatever lock serializes `block`
+    /// and [`wake`](Scheduler::wake) on `key` held, so that a wakeup cannot get
+    /// lost between the check and going to sleep. The task may also wake up
+    /// spuriously, so callers check their condition again in a loop.
+    fn block(key: usize, should_block: &mut dyn FnMut() -> bool);
+
+    /// Like [`block`](Scheduler::block), but sleeps for at most `timeout`.
+    ///
+    /// Returns what is left of `timeout`, zero if it ran out. The default is
+    /// for schedulers without a clock, and never times out.
+    fn block_timeout(
+        key: usize,
+        should_block: &mut dyn FnMut() -> bool,
+        timeout: Duration,
+    ) -> Duration {
+        Self::block(key, should_block);
+        timeout
+    }
+
+    /// Wakes up to `n` tasks sleeping on `key`, returning how many were woken.
+    fn wake(key: usize, n: usize) -> usize;
+}
+
+/// Busy-waits instead of sleeping.
+pub struct SpinWait;
+
+impl Scheduler for SpinWait {
+    /// Without a scheduler, every cpu runs a single task.
+    #[inline(always)]
+    fn current_task() -> usize {
+        cpu_id() as usize
+    }
+
+    #[inline(always)]
+    fn block(_key: usize, should_block: &mut dyn FnMut() -> bool) {
+        if should_block() {
+            spin_loop();
+        }
+    }
+
+    #[inline(always)]
+    fn wake(_key: usize, _n: usize) -> usize {
+        0
+    }
+}
+
+/// Task priorities, for the primitives that boost them. Larger is more urgent.
+///
+/// Both hooks are called with a spin lock held, so they must not block.
+pub trait PriorityScheduler: Scheduler {
+    /// Returns the priority `task` runs at when nothing boosts it.
+    fn base_priority(task: usize) -> usize;
+
+    /// Makes `task` run at `priority`, which is never below its base priority.
+    fn set_priority(task: usize, priority: usize);
+}
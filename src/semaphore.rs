@@ -0,0 +1,164 @@
+//! A counting semaphore.
+//!
+//! `Semaphore` spins until enough permits are available, which is what atomic
+//! context needs. `Semaphore<S>` with a [`Scheduler`] `S` puts the task to
+//! sleep instead.
+//!
+//! Holding permits does not turn interrupts off, so an interrupt handler must
+//! only use [`try_acquire`](Semaphore::try_acquire) on a semaphore its own cpu
+//! may be waiting for.
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::sched::{Scheduler, SpinWait};
+
+pub struct Semaphore<S = SpinWait> {
+    phantom: PhantomData<S>,
+    permits: AtomicUsize,
+    waiters: AtomicUsize, // Tasks in `acquire` that found too few permits.
+}
+
+/// An RAII implementation of permits taken from a [`Semaphore`].
+/// When this structure is dropped (falls out of scope),
+/// the permits are given back.
+///
+pub struct SemaphorePermit<'a, S: Scheduler = SpinWait> {
+    sem: &'a Semaphore<S>,
+    permits: usize,
+}
+
+unsafe impl<S> Sync for Semaphore<S> {}
+unsafe impl<S> Send for Semaphore<S> {}
+
+impl Semaphore {
+    /// Creates a spinning semaphore with `permits` permits.
+    #[inline(always)]
+    pub const fn new(permits: usize) -> Self {
+        Self::with_scheduler(permits)
+    }
+}
+
+impl<S> Semaphore<S> {
+    /// Creates a semaphore with `permits` permits, sleeping through `S`.
+    ///
+    /// ```
+    /// use lock::sched::SpinWait;
+    /// use lock::semaphore::Semaphore;
+    ///
+    /// static SEM: Semaphore<SpinWait> = Semaphore::with_scheduler(4);
+    /// ```
+    #[inline(always)]
+    pub const fn with_scheduler(permits: usize) -> Self {
+        Semaphore {
+            phantom: PhantomData,
+            permits: AtomicUsize::new(permits),
+            waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of permits available right now.
+    #[inline(always)]
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn key(&self) -> usize {
+        &self.permits as *const AtomicUsize as usize
+    }
+}
+
+impl<S: Scheduler> Semaphore<S> {
+    /// Takes `n` permits, waiting until that many are available.
+    #[inline]
+    pub fn acquire(&self, n: usize) -> SemaphorePermit<S> {
+        if let Some(permit) = self.try_acquire(n) {
+            return permit;
+        }
+        // SeqCst orders our registration before the checks of the permits,
+        // `release` does the opposite.
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let permit = loop {
+            if let Some(permit) = self.try_acquire(n) {
+                break permit;
+            }
+            S::block(self.key(), &mut || self.permits.load(Ordering::SeqCst) < n);
+        };
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        permit
+    }
+
+    /// Takes `n` permits if that many are available.
+    #[inline]
+    pub fn try_acquire(&self, n: usize) -> Option<SemaphorePermit<S>> {
+        let mut permits = self.permits.load(Ordering::Relaxed);
+        loop {
+            if permits < n {
+                return None;
+            }
+            match self.permits.compare_exchange_weak(
+                permits,
+                permits - n,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    return Some(SemaphorePermit {
+                        sem: self,
+                        permits: n,
+                    })
+                }
+                Err(x) => permits = x,
+            }
+        }
+    }
+
+    /// Adds `n` permits, waking the tasks waiting for them.
+    #[inline]
+    pub fn release(&self, n: usize) {
+        self.permits.fetch_add(n, Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) != 0 {
+            // Waiters want different amounts, let them all check.
+            S::wake(self.key(), usize::MAX);
+        }
+    }
+}
+
+impl<S> fmt::Debug for Semaphore<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Semaphore {{ permits: {} }}", self.available_permits())
+    }
+}
+
+impl<'a, S: Scheduler> SemaphorePermit<'a, S> {
+    /// Returns the number of permits held.
+    #[inline(always)]
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keeps the permits out of the semaphore for good.
+    #[inline(always)]
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl<'a, S: Scheduler> Drop for SemaphorePermit<'a, S> {
+    /// The dropping of the SemaphorePermit gives its permits back.
+    fn drop(&mut self) {
+        if self.permits != 0 {
+            self.sem.release(self.permits);
+        }
+    }
+}
+
+impl<'a, S: Scheduler> fmt::Debug for SemaphorePermit<'a, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SemaphorePermit {{ permits: {} }}", self.permits)
+    }
+}
@@ -0,0 +1,228 @@
+//! Sequence counters and sequence locks for lock-free readers.
+//!
+//! A writer makes the sequence odd while it updates the data and even again
+//! when it is done. Readers copy the data optimistically and retry if the
+//! sequence was odd or changed meanwhile, so they never write shared memory
+//! and never make a writer wait.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupt::{pop_off, push_off},
+    spin::{SpinMutex, SpinMutexGuard},
+};
+
+/// A bare sequence counter, for data whose writers are already serialized by
+/// other means.
+pub struct SeqCount {
+    seq: AtomicUsize,
+}
+
+impl SeqCount {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        SeqCount {
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts a read section, waiting for a writer in progress to finish.
+    ///
+    /// Returns the sequence to pass to [`read_retry`](SeqCount::read_retry).
+    #[inline(always)]
+    pub fn read_begin(&self) -> usize {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                return seq;
+            }
+            spin_loop();
+        }
+    }
+
+    /// Ends a read section, returning `true` if a writer got in since
+    /// `read_begin` returned `seq` and what was read must be thrown away.
+    #[inline(always)]
+    pub fn read_retry(&self, seq: usize) -> bool {
+        // Keep the reads of the data before the second load of the sequence.
+        fence(Ordering::Acquire);
+        self.seq.load(Ordering::Relaxed) != seq
+    }
+
+    /// Starts a write section.
+    ///
+    /// Interrupts stay off until the matching [`write_end`](SeqCount::write_end),
+    /// so that a reader in an interrupt handler cannot spin on this cpu's
+    /// unfinished write. Writers must not run concurrently.
+    #[inline(always)]
+    pub fn write_begin(&self) {
+        push_off();
+        let seq = self.seq.load(Ordering::Relaxed);
+        debug_assert_eq!(seq & 1, 0, "concurrent SeqCount writers");
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        // Keep the writes of the data after the odd sequence.
+        fence(Ordering::Release);
+    }
+
+    /// Ends a write section started by [`write_begin`](SeqCount::write_begin).
+    #[inline(always)]
+    pub fn write_end(&self) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        debug_assert_eq!(seq & 1, 1, "SeqCount::write_end without write_begin");
+        self.seq.store(seq.wrapping_add(1), Ordering::Release);
+        pop_off();
+    }
+}
+
+impl Default for SeqCount {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sequence lock: a [`SeqCount`] whose writers are serialized by a [`SpinMutex`].
+pub struct SeqLock<T> {
+    lock: SpinMutex<()>,
+    seq: SeqCount,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of the writer side of a [`SeqLock`].
+/// When this structure is dropped (falls out of scope),
+/// the write section ends and the lock is unlocked.
+///
+pub struct SeqLockWriteGuard<'a, T: Copy + 'a> {
+    seq: &'a SeqCount,
+    data: &'a mut T,
+    // Dropped after the write section has ended.
+    _lock: SpinMutexGuard<'a, ()>,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+
+impl<T> SeqLock<T> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        SeqLock {
+            lock: SpinMutex::new(()),
+            seq: SeqCount::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock.
+        unsafe { &mut *self.data.get() }
+    }
+}
+
+impl<T: Copy> SeqLock<T> {
+    /// Returns a copy of the data, retrying while writers get in the way.
+    #[inline(always)]
+    pub fn read(&self) -> T {
+        loop {
+            let seq = self.seq.read_begin();
+            // Safety
+            // The copy may be torn by a concurrent writer, in which case the
+            // sequence has changed and it is thrown away. A volatile read keeps
+            // the compiler from assuming the data is stable.
+            let data = unsafe { ptr::read_volatile(self.data.get()) };
+            if !self.seq.read_retry(seq) {
+                return data;
+            }
+        }
+    }
+
+    /// Locks out other writers and starts a write section.
+    #[inline(always)]
+    pub fn write(&self) -> SeqLockWriteGuard<T> {
+        let lock = self.lock.lock();
+        self.seq.write_begin();
+        SeqLockWriteGuard {
+            seq: &self.seq,
+            data: unsafe { &mut *self.data.get() },
+            _lock: lock,
+        }
+    }
+
+    /// Like [`write`](SeqLock::write), but returns `None` if another writer holds the lock.
+    #[inline(always)]
+    pub fn try_write(&self) -> Option<SeqLockWriteGuard<T>> {
+        let lock = self.lock.try_lock()?;
+        self.seq.write_begin();
+        Some(SeqLockWriteGuard {
+            seq: &self.seq,
+            data: unsafe { &mut *self.data.get() },
+            _lock: lock,
+        })
+    }
+
+    /// Replaces the data.
+    #[inline(always)]
+    pub fn set(&self, data: T) {
+        *self.write() = data;
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SeqLock {{ data: ")
+            .and_then(|()| self.read().fmt(f))
+            .and_then(|()| write!(f, "}}"))
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        SeqLock::new(T::default())
+    }
+}
+
+impl<T: Copy> From<T> for SeqLock<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: Copy> Drop for SeqLockWriteGuard<'a, T> {
+    /// The dropping of the SeqLockWriteGuard ends the write section, then unlocks.
+    fn drop(&mut self) {
+        self.seq.write_end();
+    }
+}
+
+impl<'a, T: Copy> Deref for SeqLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: Copy> DerefMut for SeqLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: Copy + fmt::Debug> fmt::Debug for SeqLockWriteGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
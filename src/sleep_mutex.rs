@@ -0,0 +1,222 @@
+//! A mutex that spins briefly, then puts the task to sleep through a [`Scheduler`].
+//!
+//! The lock word is 0 when unlocked, 1 when locked and 2 when locked with
+//! tasks possibly asleep on it, so that unlocking only calls into the
+//! scheduler when someone may need waking up.
+//!
+//! The holder may sleep, so the lock does not turn interrupts off and must not
+//! be taken from interrupt context.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    hint::spin_loop,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+
+use crate::{condvar::CondvarGuard, sched::Scheduler};
+
+const UNLOCKED: u8 = 0;
+const LOCKED: u8 = 1;
+const CONTENDED: u8 = 2;
+
+const NO_TASK: usize = usize::MAX;
+
+// How many times `lock` polls the lock before going to sleep.
+const SPIN_LIMIT: usize = 100;
+
+pub struct SleepMutex<T: ?Sized, S> {
+    phantom: PhantomData<S>,
+    state: AtomicU8,
+    owner: AtomicUsize, // Task holding the lock, for diagnostics.
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of a sleep mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be unlocked.
+///
+pub struct SleepMutexGuard<'a, T: ?Sized + 'a, S: Scheduler> {
+    mutex: &'a SleepMutex<T, S>,
+    data: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send, S> Sync for SleepMutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Send for SleepMutex<T, S> {}
+
+impl<T, S> SleepMutex<T, S> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        SleepMutex {
+            phantom: PhantomData,
+            state: AtomicU8::new(UNLOCKED),
+            owner: AtomicUsize::new(NO_TASK),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized, S: Scheduler> SleepMutex<T, S> {
+    #[inline]
+    pub fn lock(&self) -> SleepMutexGuard<T, S> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_slow();
+        }
+        self.owner.store(S::current_task(), Ordering::Relaxed);
+        SleepMutexGuard {
+            mutex: self,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    #[cold]
+    fn lock_slow(&self) {
+        debug_assert_ne!(
+            self.owner.load(Ordering::Relaxed),
+            S::current_task(),
+            "SleepMutex locked twice by the same task"
+        );
+        for _ in 0..SPIN_LIMIT {
+            if self.state.load(Ordering::Relaxed) == UNLOCKED
+                && self
+                    .state
+                    .compare_exchange_weak(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return;
+            }
+            spin_loop();
+        }
+        // We can't tell whether others are asleep once we get the lock, so
+        // keep it marked contended.
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            S::block(self.key(), &mut || {
+                self.state.load(Ordering::Relaxed) == CONTENDED
+            });
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<SleepMutexGuard<T, S>> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.owner.store(S::current_task(), Ordering::Relaxed);
+            Some(SleepMutexGuard {
+                mutex: self,
+                data: unsafe { &mut *self.data.get() },
+            })
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != UNLOCKED
+    }
+
+    #[inline(always)]
+    fn key(&self) -> usize {
+        &self.state as *const AtomicU8 as usize
+    }
+
+    fn unlock(&self) {
+        self.owner.store(NO_TASK, Ordering::Relaxed);
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            S::wake(self.key(), 1);
+        }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, S: Scheduler> fmt::Debug for SleepMutex<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "SleepMutex {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "SleepMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default, S> Default for SleepMutex<T, S> {
+    fn default() -> Self {
+        SleepMutex::new(T::default())
+    }
+}
+
+impl<T, S> From<T> for SleepMutex<T, S> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized, S: Scheduler> Drop for SleepMutexGuard<'a, T, S> {
+    /// The dropping of the SleepMutexGuard will release the lock it was created from.
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+unsafe impl<'a, T: ?Sized, S: Scheduler> CondvarGuard for SleepMutexGuard<'a, T, S> {
+    unsafe fn unlock(&mut self) {
+        self.mutex.unlock();
+    }
+
+    unsafe fn relock(&mut self) {
+        core::mem::forget(self.mutex.lock());
+    }
+}
+
+impl<'a, T: ?Sized, S: Scheduler> Deref for SleepMutexGuard<'a, T, S> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized, S: Scheduler> DerefMut for SleepMutexGuard<'a, T, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug, S: Scheduler> fmt::Debug for SleepMutexGuard<'a, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display, S: Scheduler> fmt::Display for SleepMutexGuard<'a, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
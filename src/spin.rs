@@ -6,7 +6,10 @@ use core::{
     sync::atomic::{AtomicBool, Ordering},
 };
 
-use crate::interrupt::{pop_off, push_off};
+use crate::{
+    condvar::CondvarGuard,
+    interrupt::{pop_off, push_off},
+};
 
 pub struct SpinMutex<T: ?Sized> {
     locked: AtomicBool,
@@ -134,6 +137,26 @@ impl<'a, T: ?Sized> Drop for SpinMutexGuard<'a, T> {
     }
 }
 
+unsafe impl<'a, T: ?Sized> CondvarGuard for SpinMutexGuard<'a, T> {
+    unsafe fn unlock(&mut self) {
+        self.lock.store(false, Ordering::Release);
+        pop_off();
+    }
+
+    unsafe fn relock(&mut self) {
+        push_off();
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.lock.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
 impl<'a, T: ?Sized> Deref for SpinMutexGuard<'a, T> {
     type Target = T;
     fn deref(&self) -> &T {
@@ -0,0 +1,179 @@
+//! Sleepable RCU.
+//!
+//! Every `Srcu` is its own domain: its grace periods only wait for its own
+//! readers, which may block inside their read-side critical section.
+//!
+//! Each cpu counts the read locks and read unlocks of a domain in two slots.
+//! A reader takes the slot the domain currently points at and releases it on
+//! whichever cpu it ends up on, so a slot has drained once, summed over all
+//! cpus, it has seen as many unlocks as locks. `synchronize_srcu` flips the
+//! current slot and waits for the old one to drain.
+//!
+//! The readers it waits for may sleep, and may need the updater's cpu to get
+//! done, so updaters never wait with interrupts off: they are serialized by a
+//! [`SleepMutex`] and sleep through the [`Scheduler`] `S`, woken by the
+//! readers leaving the old slot.
+
+use core::{
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{
+    interrupt::{cpu_id, MAX_CORE_NUM},
+    sched::{Scheduler, SpinWait},
+    sleep_mutex::SleepMutex,
+};
+
+#[repr(align(64))]
+struct SrcuCpu {
+    locks: [AtomicUsize; 2],   // Read locks taken on this cpu, per slot.
+    unlocks: [AtomicUsize; 2], // Read unlocks done on this cpu, per slot.
+}
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_SRCU_CPU: SrcuCpu = SrcuCpu {
+    locks: [AtomicUsize::new(0), AtomicUsize::new(0)],
+    unlocks: [AtomicUsize::new(0), AtomicUsize::new(0)],
+};
+
+pub struct Srcu<S = SpinWait> {
+    phantom: PhantomData<S>,
+    completed: AtomicUsize, // Grace periods completed, its low bit is the current slot.
+    cpus: [SrcuCpu; MAX_CORE_NUM],
+    // Serializes `synchronize_srcu` callers.
+    sync: SleepMutex<(), S>,
+    waiting: AtomicBool, // Set while an updater waits for a slot to drain.
+}
+
+/// A read-side critical section of an [`Srcu`] domain, ended when the guard is dropped.
+///
+/// Unlike an RCU read guard, it may be carried across blocking and to another cpu.
+pub struct SrcuReadGuard<'a, S: Scheduler = SpinWait> {
+    srcu: &'a Srcu<S>,
+    idx: usize,
+}
+
+impl Srcu {
+    /// Creates a domain whose updaters spin, with interrupts on.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self::with_scheduler()
+    }
+}
+
+impl<S> Srcu<S> {
+    /// Creates a domain whose updaters sleep through `S`.
+    #[inline(always)]
+    pub const fn with_scheduler() -> Self {
+        Srcu {
+            phantom: PhantomData,
+            completed: AtomicUsize::new(0),
+            cpus: [DEFAULT_SRCU_CPU; MAX_CORE_NUM],
+            sync: SleepMutex::new(()),
+            waiting: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the number of grace periods this domain has completed.
+    #[inline(always)]
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn key(&self) -> usize {
+        &self.waiting as *const AtomicBool as usize
+    }
+}
+
+impl<S: Scheduler> Srcu<S> {
+    /// Enters a read-side critical section of this domain.
+    #[inline(always)]
+    pub fn srcu_read_lock(&self) -> SrcuReadGuard<S> {
+        let idx = self.completed.load(Ordering::Relaxed) & 1;
+        self.cpus[cpu_id() as usize].locks[idx].fetch_add(1, Ordering::Relaxed);
+        // Keep the accesses of the section after the increment.
+        fence(Ordering::SeqCst);
+        SrcuReadGuard { srcu: self, idx }
+    }
+
+    /// Leaves a read-side critical section of this domain.
+    #[inline(always)]
+    pub fn srcu_read_unlock(&self, guard: SrcuReadGuard<S>) {
+        debug_assert!(core::ptr::eq(self, guard.srcu));
+        drop(guard);
+    }
+
+    /// Waits until all read-side critical sections of this domain that were
+    /// running when it was called have ended.
+    ///
+    /// Must not be called inside a read-side critical section of the same domain.
+    pub fn synchronize_srcu(&self) {
+        let _sync = self.sync.lock();
+        // Order the caller's updates before the checks of the counters.
+        fence(Ordering::SeqCst);
+        // A reader may have picked the other slot before the previous flip and
+        // only incremented it now. Wait for those first, so that the slot we
+        // flip to is empty apart from new readers.
+        let completed = self.completed.load(Ordering::Relaxed);
+        self.wait_drained((completed & 1) ^ 1);
+        self.completed
+            .store(completed.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        self.wait_drained(completed & 1);
+    }
+
+    fn wait_drained(&self, idx: usize) {
+        self.waiting.store(true, Ordering::Relaxed);
+        // Either a leaving reader sees us waiting, or we see it gone.
+        fence(Ordering::SeqCst);
+        while !self.drained(idx) {
+            S::block(self.key(), &mut || !self.drained(idx));
+        }
+        self.waiting.store(false, Ordering::Relaxed);
+        // Order the readers' accesses before whatever the caller does next.
+        fence(Ordering::SeqCst);
+    }
+
+    /// Has every read lock of slot `idx` been matched by a read unlock?
+    fn drained(&self, idx: usize) -> bool {
+        // Sum the unlocks first: a reader counted there but not in the locks
+        // would be missed otherwise.
+        let unlocks = self.cpus.iter().fold(0usize, |sum, cpu| {
+            sum.wrapping_add(cpu.unlocks[idx].load(Ordering::Relaxed))
+        });
+        fence(Ordering::SeqCst);
+        let locks = self.cpus.iter().fold(0usize, |sum, cpu| {
+            sum.wrapping_add(cpu.locks[idx].load(Ordering::Relaxed))
+        });
+        locks == unlocks
+    }
+}
+
+impl<S> Default for Srcu<S> {
+    fn default() -> Self {
+        Self::with_scheduler()
+    }
+}
+
+impl<S> fmt::Debug for Srcu<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Srcu {{ completed: {} }}", self.completed())
+    }
+}
+
+impl<'a, S: Scheduler> Drop for SrcuReadGuard<'a, S> {
+    fn drop(&mut self) {
+        // Keep the accesses of the section before the increment.
+        fence(Ordering::SeqCst);
+        self.srcu.cpus[cpu_id() as usize].unlocks[self.idx].fetch_add(1, Ordering::Relaxed);
+        // Pairs with the fence in `wait_drained`.
+        fence(Ordering::SeqCst);
+        if self.srcu.waiting.load(Ordering::Relaxed) {
+            S::wake(self.srcu.key(), 1);
+        }
+    }
+}
@@ -6,7 +6,10 @@ use core::{
     sync::atomic::{AtomicUsize, Ordering},
 };
 
-use crate::interrupt::{pop_off, push_off};
+use crate::{
+    condvar::CondvarGuard,
+    interrupt::{pop_off, push_off},
+};
 
 pub struct TicketMutex<T: ?Sized> {
     next_ticket: AtomicUsize,
@@ -19,6 +22,7 @@ pub struct TicketMutex<T: ?Sized> {
 /// the lock will be unlocked.
 ///
 pub struct TicketMutexGuard<'a, T: ?Sized + 'a> {
+    next_ticket: &'a AtomicUsize,
     next_serving: &'a AtomicUsize,
     ticket: usize,
     data: &'a mut T,
@@ -59,6 +63,7 @@ impl<T: ?Sized> TicketMutex<T> {
             core::hint::spin_loop();
         }
         TicketMutexGuard {
+            next_ticket: &self.next_ticket,
             next_serving: &self.next_serving,
             ticket,
             // Safety
@@ -85,6 +90,7 @@ impl<T: ?Sized> TicketMutex<T> {
             });
         if let Ok(ticket) = ticket {
             Some(TicketMutexGuard {
+                next_ticket: &self.next_ticket,
                 next_serving: &self.next_serving,
                 ticket,
                 // Safety
@@ -122,6 +128,21 @@ impl<'a, T: ?Sized> Drop for TicketMutexGuard<'a, T> {
     }
 }
 
+unsafe impl<'a, T: ?Sized> CondvarGuard for TicketMutexGuard<'a, T> {
+    unsafe fn unlock(&mut self) {
+        self.next_serving.store(self.ticket + 1, Ordering::Release);
+        pop_off();
+    }
+
+    unsafe fn relock(&mut self) {
+        push_off();
+        self.ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.next_serving.load(Ordering::Acquire) != self.ticket {
+            core::hint::spin_loop();
+        }
+    }
+}
+
 impl<T: ?Sized + fmt::Debug> fmt::Debug for TicketMutex<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self.try_lock() {
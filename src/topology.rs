@@ -0,0 +1,45 @@
+//! Mapping from cpu ids to NUMA nodes.
+//!
+//! The kernel registers the topology once at boot, before any NUMA-aware lock
+//! is contended. Until then every cpu is reported on node 0.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::interrupt::{cpu_id, MAX_CORE_NUM};
+
+/// The maximum number of NUMA nodes a topology may describe.
+pub const MAX_NUMA_NODES: usize = 8;
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_NODE: AtomicU8 = AtomicU8::new(0);
+
+static CPU_NODE: [AtomicU8; MAX_CORE_NUM] = [DEFAULT_NODE; MAX_CORE_NUM];
+
+/// Registers the node of every cpu at once, `nodes[cpu]` being the node of `cpu`.
+///
+/// Cpus not covered by `nodes` are left untouched.
+pub fn register_topology(nodes: &[u8]) {
+    assert!(nodes.len() <= MAX_CORE_NUM, "too many cpus in topology");
+    for (cpu, &node) in nodes.iter().enumerate() {
+        set_cpu_node(cpu, node as usize);
+    }
+}
+
+/// Places `cpu` on NUMA node `node`.
+pub fn set_cpu_node(cpu: usize, node: usize) {
+    assert!(node < MAX_NUMA_NODES, "numa node out of range");
+    CPU_NODE[cpu].store(node as u8, Ordering::Relaxed);
+}
+
+/// Returns the NUMA node of `cpu`.
+#[inline(always)]
+pub fn cpu_node(cpu: usize) -> usize {
+    CPU_NODE[cpu].load(Ordering::Relaxed) as usize
+}
+
+/// Returns the NUMA node of the current cpu.
+#[inline(always)]
+pub fn current_node() -> usize {
+    cpu_node(cpu_id() as usize)
+}
@@ -0,0 +1,256 @@
+//! A ticket lock with a waiting array (TWA).
+//!
+//! Like [`TicketMutex`](crate::ticket::TicketMutex), the lock is granted in
+//! ticket order, but only the immediate successor spins on `next_serving`.
+//! Waiters further back in the queue spin on a slot of a global waiting array
+//! chosen by hashing the lock address and adding their ticket, so the waiters
+//! of one lock never share a slot. Releasing the lock
+//! bumps the slot of the waiter that just became the immediate successor, so a
+//! contended release invalidates two cache lines instead of one per waiter.
+
+use core::{
+    cell::UnsafeCell,
+    default::Default,
+    fmt,
+    hint::spin_loop,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::interrupt::{pop_off, push_off};
+
+const WAIT_ARRAY_BITS: u32 = 12;
+const WAIT_ARRAY_SIZE: usize = 1 << WAIT_ARRAY_BITS;
+
+// Waiters whose distance to `next_serving` is at most this spin on the lock itself.
+const LONG_TERM_THRESHOLD: usize = 1;
+
+// Avoid hard code
+#[allow(clippy::declare_interior_mutable_const)]
+const DEFAULT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+// Shared by all locks, a collision only causes a spurious wakeup.
+static WAIT_ARRAY: [AtomicUsize; WAIT_ARRAY_SIZE] = [DEFAULT_SLOT; WAIT_ARRAY_SIZE];
+
+// The golden ratio as a fraction of the `usize` range, for Fibonacci hashing.
+const GOLDEN_RATIO: usize = (0x9e37_79b9_7f4a_7c15_u64 >> (64 - usize::BITS)) as usize;
+
+#[inline(always)]
+fn wait_index(lock: *const AtomicUsize, ticket: usize) -> usize {
+    // The hash of the lock picks where its tickets start, and consecutive
+    // tickets take consecutive slots from there.
+    let base = (lock as usize).wrapping_mul(GOLDEN_RATIO) >> (usize::BITS - WAIT_ARRAY_BITS);
+    base.wrapping_add(ticket) % WAIT_ARRAY_SIZE
+}
+
+#[inline(always)]
+fn wait_slot(lock: *const AtomicUsize, ticket: usize) -> &'static AtomicUsize {
+    &WAIT_ARRAY[wait_index(lock, ticket)]
+}
+
+pub struct TwaMutex<T: ?Sized> {
+    next_ticket: AtomicUsize,
+    next_serving: AtomicUsize,
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of a mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be unlocked.
+///
+pub struct TwaMutexGuard<'a, T: ?Sized + 'a> {
+    next_serving: &'a AtomicUsize,
+    ticket: usize,
+    data: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for TwaMutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for TwaMutex<T> {}
+
+impl<T> TwaMutex<T> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        TwaMutex {
+            next_ticket: AtomicUsize::new(0),
+            next_serving: AtomicUsize::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+
+    #[inline(always)]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+}
+
+impl<T: ?Sized> TwaMutex<T> {
+    #[inline(always)]
+    pub fn lock(&self) -> TwaMutexGuard<T> {
+        push_off();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        if self.next_serving.load(Ordering::Acquire) != ticket {
+            self.wait_long_term(ticket);
+            while self.next_serving.load(Ordering::Acquire) != ticket {
+                spin_loop();
+            }
+        }
+        TwaMutexGuard {
+            next_serving: &self.next_serving,
+            ticket,
+            // Safety
+            // We know that we are the next ticket to be served,
+            // so there's no other thread accessing the data.
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    /// Spins on our waiting array slot until we are the immediate successor.
+    #[inline(always)]
+    fn wait_long_term(&self, ticket: usize) {
+        let slot = wait_slot(&self.next_serving, ticket);
+        loop {
+            // Read the slot before `next_serving`, so that a release that
+            // happens in between is seen as a change of the slot.
+            let seq = slot.load(Ordering::Acquire);
+            let distance = ticket.wrapping_sub(self.next_serving.load(Ordering::Acquire));
+            if distance <= LONG_TERM_THRESHOLD {
+                return;
+            }
+            while slot.load(Ordering::Acquire) == seq {
+                spin_loop();
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<TwaMutexGuard<T>> {
+        push_off();
+        let ticket = self
+            .next_ticket
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |ticket| {
+                if self.next_serving.load(Ordering::Acquire) == ticket {
+                    Some(ticket + 1)
+                } else {
+                    None
+                }
+            });
+        if let Ok(ticket) = ticket {
+            Some(TwaMutexGuard {
+                next_serving: &self.next_serving,
+                ticket,
+                // Safety
+                // We have a ticket that is equal to the next_serving ticket, so we know:
+                // - that no other thread can have the same ticket id as this thread
+                // - that we are the next one to be served so we have exclusive access to the data
+                data: unsafe { &mut *self.data.get() },
+            })
+        } else {
+            pop_off();
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        let ticket = self.next_ticket.load(Ordering::Relaxed);
+        self.next_serving.load(Ordering::Relaxed) != ticket
+    }
+}
+
+impl<'a, T: ?Sized> Drop for TwaMutexGuard<'a, T> {
+    /// The dropping of the TwaMutexGuard will release the lock it was created from,
+    /// and wake up the waiter that became the immediate successor.
+    fn drop(&mut self) {
+        let new_ticket = self.ticket.wrapping_add(1);
+        self.next_serving.store(new_ticket, Ordering::Release);
+        wait_slot(
+            self.next_serving,
+            new_ticket.wrapping_add(LONG_TERM_THRESHOLD),
+        )
+        .fetch_add(1, Ordering::Release);
+        pop_off();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TwaMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "Mutex {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "Mutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default> Default for TwaMutex<T> {
+    fn default() -> Self {
+        TwaMutex::new(T::default())
+    }
+}
+
+impl<T> From<T> for TwaMutex<T> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display> fmt::Display for TwaMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for TwaMutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized> Deref for TwaMutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for TwaMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn wait_index_test() {
+        let lock = AtomicUsize::new(0);
+        // Consecutive tickets of one lock spin on different slots, across the
+        // wrap of the ticket counter as well.
+        for start in [0, 12345, usize::MAX - 100] {
+            let mut slots: Vec<_> = (0..WAIT_ARRAY_SIZE)
+                .map(|i| wait_index(&lock, start.wrapping_add(i)))
+                .collect();
+            slots.sort_unstable();
+            slots.dedup();
+            assert_eq!(slots.len(), WAIT_ARRAY_SIZE);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! A queue of tasks waiting for an event.
+//!
+//! A waiter queues itself, then checks its condition once more before going
+//! to sleep, so a wakeup sent between its first check and queueing is not
+//! lost. Wakers set the flag of each waiter they take off the queue and wake
+//! it through the [`Scheduler`].
+//!
+//! Exclusive waiters are woken one at a time by [`WaitQueue::wake_up_one`],
+//! while every non-exclusive waiter is woken by any wakeup, as in Linux.
+
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    sched::{Scheduler, SpinWait},
+    spin::SpinMutex,
+};
+
+struct Waiter {
+    woken: *const AtomicBool, // Lives on the waiter's stack until it leaves the queue.
+    exclusive: bool,
+}
+
+// #Safety: The flag is only touched under the queue lock, while its waiter is queued.
+unsafe impl Send for Waiter {}
+
+impl Waiter {
+    fn key(&self) -> usize {
+        self.woken as usize
+    }
+}
+
+pub struct WaitQueue<S = SpinWait> {
+    phantom: PhantomData<S>,
+    waiters: SpinMutex<Vec<Waiter>>,
+}
+
+unsafe impl<S> Sync for WaitQueue<S> {}
+unsafe impl<S> Send for WaitQueue<S> {}
+
+impl WaitQueue {
+    /// Creates a wait queue whose waiters spin.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self::with_scheduler()
+    }
+}
+
+impl<S> WaitQueue<S> {
+    /// Creates a wait queue whose waiters sleep through `S`.
+    #[inline(always)]
+    pub const fn with_scheduler() -> Self {
+        WaitQueue {
+            phantom: PhantomData,
+            waiters: SpinMutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the number of waiters queued.
+    pub fn len(&self) -> usize {
+        self.waiters.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<S: Scheduler> WaitQueue<S> {
+    /// Sleeps until `condition` returns `true`.
+    pub fn wait_event<F: FnMut() -> bool>(&self, condition: F) {
+        self.wait(condition, false, None);
+    }
+
+    /// Sleeps until `condition` returns `true`, as an exclusive waiter.
+    pub fn wait_event_exclusive<F: FnMut() -> bool>(&self, condition: F) {
+        self.wait(condition, true, None);
+    }
+
+    /// Sleeps until `condition` returns `true` or `timeout` runs out.
+    ///
+    /// Returns the final value of `condition`.
+    pub fn wait_event_timeout<F: FnMut() -> bool>(&self, condition: F, timeout: Duration) -> bool {
+        self.wait(condition, false, Some(timeout))
+    }
+
+    /// Like [`wait_event_timeout`](WaitQueue::wait_event_timeout), as an exclusive waiter.
+    pub fn wait_event_exclusive_timeout<F: FnMut() -> bool>(
+        &self,
+        condition: F,
+        timeout: Duration,
+    ) -> bool {
+        self.wait(condition, true, Some(timeout))
+    }
+
+    fn wait<F: FnMut() -> bool>(
+        &self,
+        mut condition: F,
+        exclusive: bool,
+        mut timeout: Option<Duration>,
+    ) -> bool {
+        loop {
+            if condition() {
+                return true;
+            }
+            if timeout == Some(Duration::ZERO) {
+                return false;
+            }
+            let woken = AtomicBool::new(false);
+            let key = &woken as *const AtomicBool as usize;
+            self.waiters.lock().push(Waiter {
+                woken: &woken,
+                exclusive,
+            });
+            if !condition() {
+                let mut should_block = || !woken.load(Ordering::Acquire);
+                match timeout {
+                    Some(left) => {
+                        timeout = Some(S::block_timeout(key, &mut should_block, left));
+                    }
+                    None => S::block(key, &mut should_block),
+                }
+            }
+            let mut waiters = self.waiters.lock();
+            let queued = waiters.iter().position(|waiter| waiter.key() == key);
+            match queued {
+                Some(index) => {
+                    waiters.remove(index);
+                }
+                None if exclusive && timeout == Some(Duration::ZERO) && !condition() => {
+                    // We are giving up with an exclusive wakeup, pass it on.
+                    drop(waiters);
+                    self.wake_up(1);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Wakes every non-exclusive waiter and one exclusive waiter.
+    ///
+    /// Returns the number of waiters woken.
+    pub fn wake_up_one(&self) -> usize {
+        self.wake_up(1)
+    }
+
+    /// Wakes every waiter. Returns the number of waiters woken.
+    pub fn wake_up_all(&self) -> usize {
+        self.wake_up(usize::MAX)
+    }
+
+    /// Wakes every non-exclusive waiter and up to `exclusive` exclusive waiters.
+    ///
+    /// Returns the number of waiters woken.
+    pub fn wake_up(&self, mut exclusive: usize) -> usize {
+        let mut woken = 0;
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|waiter| {
+            if waiter.exclusive {
+                if exclusive == 0 {
+                    return true;
+                }
+                exclusive -= 1;
+            }
+            // The waiter cannot leave before we release the queue lock.
+            unsafe { (*waiter.woken).store(true, Ordering::Release) };
+            S::wake(waiter.key(), 1);
+            woken += 1;
+            false
+        });
+        woken
+    }
+}
+
+impl<S> Default for WaitQueue<S> {
+    fn default() -> Self {
+        Self::with_scheduler()
+    }
+}
+
+impl<S> fmt::Debug for WaitQueue<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WaitQueue {{ waiters: {} }}", self.len())
+    }
+}
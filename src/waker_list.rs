@@ -0,0 +1,122 @@
+//! A FIFO list of waiting futures, for the async primitives that interrupt
+//! handlers signal.
+//!
+//! A waiter queues itself when it is polled, which may allocate. Signalling
+//! picks waiters and wakes them in place, without allocating, so it only needs
+//! the spin lock the list lives under. A picked waiter stays on the list until
+//! its future is polled or dropped, so a future dropped after being picked can
+//! pass on what it was given.
+//!
+//! What this lets interrupt handlers do, and asks of wakers, is in the crate
+//! docs.
+
+use alloc::vec::Vec;
+use core::task::Waker;
+
+struct Waiter<D> {
+    id: usize,
+    data: D,
+    waker: Option<Waker>, // None once picked.
+}
+
+pub(crate) struct WakerList<D = ()> {
+    waiters: Vec<Waiter<D>>, // In arrival order.
+    next_id: usize,
+}
+
+impl<D> WakerList<D> {
+    pub(crate) const fn new() -> Self {
+        WakerList {
+            waiters: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Returns the number of waiters not picked yet.
+    pub(crate) fn waiting(&self) -> usize {
+        self.waiters.iter().filter(|w| w.waker.is_some()).count()
+    }
+
+    /// Queues a waiter, returning its id.
+    pub(crate) fn push(&mut self, data: D, waker: &Waker) -> usize {
+        let id = self.next_id;
+        self.next_id = id.wrapping_add(1);
+        self.waiters.push(Waiter {
+            id,
+            data,
+            waker: Some(waker.clone()),
+        });
+        id
+    }
+
+    /// Keeps the waker of a queued waiter current.
+    pub(crate) fn update(&mut self, id: usize, waker: &Waker) {
+        if let Some(Waiter {
+            waker: Some(old), ..
+        }) = self.waiters.iter_mut().find(|w| w.id == id)
+        {
+            if !old.will_wake(waker) {
+                *old = waker.clone();
+            }
+        }
+    }
+
+    /// Returns the data of the first waiter not picked yet.
+    pub(crate) fn front(&self) -> Option<&D> {
+        self.waiters
+            .iter()
+            .find(|w| w.waker.is_some())
+            .map(|w| &w.data)
+    }
+
+    /// Returns the data of the first waiter not picked yet, to change it
+    /// before picking it.
+    pub(crate) fn front_mut(&mut self) -> Option<&mut D> {
+        self.waiters
+            .iter_mut()
+            .find(|w| w.waker.is_some())
+            .map(|w| &mut w.data)
+    }
+
+    /// Picks and wakes the first waiter not picked yet, returning whether there was one.
+    pub(crate) fn wake_front(&mut self) -> bool {
+        match self.waiters.iter_mut().find_map(|w| w.waker.take()) {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Picks and wakes every waiter, returning how many there were.
+    pub(crate) fn wake_all(&mut self) -> usize {
+        let mut woken = 0;
+        for waker in self.waiters.iter_mut().filter_map(|w| w.waker.take()) {
+            waker.wake();
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Returns whether `id` has been picked, taking it off the list if so.
+    pub(crate) fn take_picked(&mut self, id: usize) -> bool {
+        match self.waiters.iter().position(|w| w.id == id) {
+            Some(index) if self.waiters[index].waker.is_none() => {
+                self.waiters.remove(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes `id` off the list, returning its data if it had been picked.
+    pub(crate) fn remove(&mut self, id: usize) -> Option<D> {
+        let index = self.waiters.iter().position(|w| w.id == id)?;
+        let waiter = self.waiters.remove(index);
+        match waiter.waker {
+            Some(_) => None,
+            None => Some(waiter.data),
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! Wound/wait mutexes, for taking sets of locks in any order.
+//!
+//! Each attempt at taking a set of locks runs under a [`WwAcquireCtx`], stamped
+//! from its [`WwClass`] by a ticket counter, so smaller stamps are older. When a
+//! context finds a lock held by a younger context, it wounds the holder and
+//! waits; a younger context simply waits for an older one. A wounded context
+//! gets [`WwError::Deadlock`] from its next contended `lock`, or while waiting,
+//! and must then drop every lock it holds, wait for the contended one with
+//! [`WwMutex::lock_slow`] and retry with the same context. The oldest context
+//! never backs off, so every attempt finishes eventually.
+//!
+//! ```
+//! use lock::sched::SpinWait;
+//! use lock::ww_mutex::{WwAcquireCtx, WwClass, WwMutex, WwMutexGuard};
+//!
+//! type Buffer = WwMutex<Vec<u8>, SpinWait>;
+//! type BufferGuard<'a> = WwMutexGuard<'a, Vec<u8>, SpinWait>;
+//!
+//! // Locks both buffers, whatever order other tasks lock them in.
+//! fn lock_pair<'a>(
+//!     ctx: &'a WwAcquireCtx,
+//!     mut first: &'a Buffer,
+//!     mut second: &'a Buffer,
+//! ) -> (BufferGuard<'a>, BufferGuard<'a>) {
+//!     // Holding nothing, we are never told to back off.
+//!     let mut held = first.lock(ctx).unwrap();
+//!     loop {
+//!         match second.lock(ctx) {
+//!             Ok(guard) => return (held, guard),
+//!             Err(_) => {
+//!                 // Back off, then wait for the contended lock before the other.
+//!                 drop(held);
+//!                 held = second.lock_slow(ctx);
+//!                 core::mem::swap(&mut first, &mut second);
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! static CLASS: WwClass = WwClass::new();
+//! let (a, b) = (Buffer::new(vec![1]), Buffer::new(vec![2]));
+//! let ctx = WwAcquireCtx::new(&CLASS);
+//! let (x, y) = lock_pair(&ctx, &a, &b);
+//! ctx.done();
+//! assert_eq!(x.len() + y.len(), 2);
+//! ```
+//!
+//! The holder may sleep, so these locks do not turn interrupts off and must not
+//! be taken from interrupt context.
+
+use alloc::sync::Arc;
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{sched::Scheduler, spin::SpinMutex};
+
+// A context waiting on nothing.
+const NO_KEY: usize = 0;
+
+/// The source of stamps for the contexts locking one family of `WwMutex`es.
+pub struct WwClass {
+    next_stamp: AtomicUsize,
+}
+
+impl WwClass {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        WwClass {
+            next_stamp: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline(always)]
+    fn stamp(&self) -> usize {
+        self.next_stamp.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl Default for WwClass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The part of a context that other tasks look at.
+///
+/// The locks a context holds keep a reference to it, so a guard leaked with
+/// `mem::forget` can't leave them pointing at a context that is gone.
+struct CtxShared {
+    stamp: usize,
+    wounded: AtomicBool, // Set by an older context that wants one of our locks.
+    waiting_on: AtomicUsize, // Key of the lock we sleep on, for wounders to wake us.
+}
+
+impl CtxShared {
+    /// Is `self` older than `other`? Stamps wrap around, like tickets.
+    #[inline(always)]
+    fn is_older(&self, other: &CtxShared) -> bool {
+        (self.stamp.wrapping_sub(other.stamp) as isize) < 0
+    }
+
+    /// Tells the younger holder of a lock we want to back off.
+    fn wound<S: Scheduler>(&self) {
+        self.wounded.store(true, Ordering::SeqCst);
+        let key = self.waiting_on.load(Ordering::SeqCst);
+        if key != NO_KEY {
+            S::wake(key, usize::MAX);
+        }
+    }
+}
+
+/// One attempt at taking a set of `WwMutex`es, kept across retries.
+pub struct WwAcquireCtx {
+    shared: Arc<CtxShared>,
+    acquired: Cell<usize>, // Locks held, only touched by our own task.
+    done: Cell<bool>,      // Set by `done()`.
+}
+
+impl WwAcquireCtx {
+    /// Starts an attempt with a fresh stamp from `class`.
+    pub fn new(class: &WwClass) -> Self {
+        WwAcquireCtx {
+            shared: Arc::new(CtxShared {
+                stamp: class.stamp(),
+                wounded: AtomicBool::new(false),
+                waiting_on: AtomicUsize::new(NO_KEY),
+            }),
+            acquired: Cell::new(0),
+            done: Cell::new(false),
+        }
+    }
+
+    /// Returns the stamp of this context. Smaller stamps are older.
+    #[inline(always)]
+    pub fn stamp(&self) -> usize {
+        self.shared.stamp
+    }
+
+    /// Returns the number of locks held under this context.
+    #[inline(always)]
+    pub fn acquired(&self) -> usize {
+        self.acquired.get()
+    }
+
+    /// Marks the end of the locking phase. No more locks may be taken under
+    /// this context, so it can no longer be told to back off.
+    pub fn done(&self) {
+        self.done.set(true);
+    }
+
+    #[inline(always)]
+    fn must_back_off(&self) -> bool {
+        self.acquired.get() != 0 && self.shared.wounded.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for WwAcquireCtx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "WwAcquireCtx {{ stamp: {}, acquired: {} }}",
+            self.stamp(),
+            self.acquired.get()
+        )
+    }
+}
+
+/// The error returned when a lock can't be taken under a context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WwError {
+    /// The context was wounded and must drop its locks before retrying.
+    Deadlock,
+    /// The lock is already held under this context.
+    Already,
+}
+
+impl fmt::Display for WwError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WwError::Deadlock => write!(f, "WwMutex deadlock, back off and retry"),
+            WwError::Already => write!(f, "WwMutex already locked by this context"),
+        }
+    }
+}
+
+// The context holding the lock, none if unlocked or locked without a context.
+type Holder = Option<Arc<CtxShared>>;
+
+pub struct WwMutex<T: ?Sized, S> {
+    phantom: PhantomData<S>,
+    locked: AtomicBool,
+    holder: SpinMutex<Holder>, // Serializes locking, unlocking and wounding.
+    data: UnsafeCell<T>,
+}
+
+/// An RAII implementation of a “scoped lock” of a wound/wait mutex.
+/// When this structure is dropped (falls out of scope),
+/// the lock will be unlocked.
+///
+pub struct WwMutexGuard<'a, T: ?Sized + 'a, S: Scheduler> {
+    mutex: &'a WwMutex<T, S>,
+    ctx: Option<&'a WwAcquireCtx>,
+    data: &'a mut T,
+}
+
+unsafe impl<T: ?Sized + Send, S> Sync for WwMutex<T, S> {}
+unsafe impl<T: ?Sized + Send, S> Send for WwMutex<T, S> {}
+
+impl<T, S> WwMutex<T, S> {
+    #[inline(always)]
+    pub const fn new(data: T) -> Self {
+        WwMutex {
+            phantom: PhantomData,
+            locked: AtomicBool::new(false),
+            holder: SpinMutex::new(None),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    #[inline(always)]
+    pub fn into_inner(self) -> T {
+        // We know statically that there are no outstanding references to
+        // `self` so there's no need to lock.
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized, S: Scheduler> WwMutex<T, S> {
+    /// Locks the mutex under `ctx`, wounding a younger holder and waiting.
+    ///
+    /// Returns [`WwError::Deadlock`] if `ctx` was wounded, and
+    /// [`WwError::Already`] if `ctx` holds the lock already.
+    pub fn lock<'a>(&'a self, ctx: &'a WwAcquireCtx) -> Result<WwMutexGuard<'a, T, S>, WwError> {
+        debug_assert!(!ctx.done.get(), "WwMutex locked after WwAcquireCtx::done");
+        if ctx.acquired.get() == 0 {
+            // Holding nothing, we have nothing to give back.
+            ctx.shared.wounded.store(false, Ordering::Relaxed);
+        }
+        loop {
+            {
+                let mut holder = self.holder.lock();
+                if !self.locked.load(Ordering::Relaxed) {
+                    return Ok(self.acquire(&mut holder, Some(ctx)));
+                }
+                if let Some(other) = holder.as_ref() {
+                    if Arc::ptr_eq(other, &ctx.shared) {
+                        return Err(WwError::Already);
+                    }
+                }
+                if ctx.must_back_off() {
+                    return Err(WwError::Deadlock);
+                }
+                if let Some(other) = holder.as_ref() {
+                    if ctx.shared.is_older(other) {
+                        other.wound::<S>();
+                    }
+                }
+                ctx.shared.waiting_on.store(self.key(), Ordering::SeqCst);
+            }
+            S::block(self.key(), &mut || {
+                self.locked.load(Ordering::Relaxed) && !ctx.must_back_off()
+            });
+            ctx.shared.waiting_on.store(NO_KEY, Ordering::SeqCst);
+        }
+    }
+
+    /// Waits for the mutex under `ctx` after backing off.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ctx` still holds locks.
+    pub fn lock_slow<'a>(&'a self, ctx: &'a WwAcquireCtx) -> WwMutexGuard<'a, T, S> {
+        assert_eq!(
+            ctx.acquired.get(),
+            0,
+            "WwMutex::lock_slow called without backing off"
+        );
+        match self.lock(ctx) {
+            Ok(guard) => guard,
+            // Holding nothing, we are never told to back off.
+            Err(err) => unreachable!("{}", err),
+        }
+    }
+
+    /// Locks the mutex if it is free, outside of any context.
+    pub fn try_lock(&self) -> Option<WwMutexGuard<T, S>> {
+        let mut holder = self.holder.lock();
+        if self.locked.load(Ordering::Relaxed) {
+            None
+        } else {
+            Some(self.acquire(&mut holder, None))
+        }
+    }
+
+    #[inline(always)]
+    pub fn get_mut(&mut self) -> &mut T {
+        // We know statically that there are no other references to `self`, so
+        // there's no need to lock the inner mutex.
+        unsafe { &mut *self.data.get() }
+    }
+
+    #[inline(always)]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn key(&self) -> usize {
+        &self.locked as *const AtomicBool as usize
+    }
+
+    fn acquire<'a>(
+        &'a self,
+        holder: &mut Holder,
+        ctx: Option<&'a WwAcquireCtx>,
+    ) -> WwMutexGuard<'a, T, S> {
+        self.locked.store(true, Ordering::Relaxed);
+        *holder = ctx.map(|ctx| {
+            ctx.acquired.set(ctx.acquired.get() + 1);
+            ctx.shared.clone()
+        });
+        WwMutexGuard {
+            mutex: self,
+            ctx,
+            data: unsafe { &mut *self.data.get() },
+        }
+    }
+
+    fn unlock(&self, ctx: Option<&WwAcquireCtx>) {
+        {
+            let mut holder = self.holder.lock();
+            *holder = None;
+            self.locked.store(false, Ordering::Release);
+        }
+        if let Some(ctx) = ctx {
+            ctx.acquired.set(ctx.acquired.get() - 1);
+        }
+        // Wake everyone, as a wounded waiter would not pass a single wakeup on.
+        S::wake(self.key(), usize::MAX);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug, S: Scheduler> fmt::Debug for WwMutex<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => write!(f, "WwMutex {{ data: ")
+                .and_then(|()| (&*guard).fmt(f))
+                .and_then(|()| write!(f, "}}")),
+            None => write!(f, "WwMutex {{ <locked> }}"),
+        }
+    }
+}
+
+impl<T: ?Sized + Default, S> Default for WwMutex<T, S> {
+    fn default() -> Self {
+        WwMutex::new(T::default())
+    }
+}
+
+impl<T, S> From<T> for WwMutex<T, S> {
+    fn from(data: T) -> Self {
+        Self::new(data)
+    }
+}
+
+impl<'a, T: ?Sized, S: Scheduler> Drop for WwMutexGuard<'a, T, S> {
+    /// The dropping of the WwMutexGuard will release the lock it was created from.
+    fn drop(&mut self) {
+        self.mutex.unlock(self.ctx);
+    }
+}
+
+impl<'a, T: ?Sized, S: Scheduler> Deref for WwMutexGuard<'a, T, S> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized, S: Scheduler> DerefMut for WwMutexGuard<'a, T, S> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.data
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Debug, S: Scheduler> fmt::Debug for WwMutexGuard<'a, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<'a, T: ?Sized + fmt::Display, S: Scheduler> fmt::Display for WwMutexGuard<'a, T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
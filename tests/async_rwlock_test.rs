@@ -0,0 +1,173 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::executor::{block_on, poll_once, Executor};
+use lock::async_rwlock::AsyncRwLock;
+use std::task::Poll;
+
+#[test]
+fn try_test() {
+    let lock = AsyncRwLock::new(0);
+    let r1 = lock.try_read().unwrap();
+    let r2 = lock.try_read().unwrap();
+    assert!(lock.try_write().is_none());
+    let upgradable = lock.try_upgradeable_read().unwrap();
+    assert!(lock.try_upgradeable_read().is_none());
+    assert_eq!(lock.reader_count(), 3);
+    drop(r1);
+    let upgradable = upgradable.try_upgrade().unwrap_err();
+    drop(r2);
+    let mut writer = upgradable.try_upgrade().unwrap();
+    *writer += 1;
+    assert_eq!(lock.writer_count(), 1);
+    assert!(lock.try_read().is_none());
+    let reader = writer.downgrade();
+    assert_eq!(*reader, 1);
+    assert_eq!(lock.reader_count(), 1);
+    drop(reader);
+    assert_eq!(lock.reader_count() + lock.writer_count(), 0);
+}
+
+#[test]
+fn writer_not_overtaken_test() {
+    let lock = AsyncRwLock::new(0);
+    let reader = lock.try_read().unwrap();
+    let mut writer = Box::pin(lock.write());
+    assert!(poll_once(&mut writer).is_pending());
+    // A reader arriving after the writer queues behind it.
+    let mut late_reader = Box::pin(lock.read());
+    assert!(poll_once(&mut late_reader).is_pending());
+    assert!(lock.try_read().is_none());
+    drop(reader);
+    assert!(poll_once(&mut late_reader).is_pending());
+    let mut guard = match poll_once(&mut writer) {
+        Poll::Ready(guard) => guard,
+        Poll::Pending => panic!("the writer was not woken"),
+    };
+    *guard += 1;
+    drop(guard);
+    match poll_once(&mut late_reader) {
+        Poll::Ready(guard) => assert_eq!(*guard, 1),
+        Poll::Pending => panic!("the reader was not woken"),
+    };
+}
+
+#[test]
+fn reader_batch_test() {
+    let lock = AsyncRwLock::new(());
+    let writer = lock.try_write().unwrap();
+    let mut futures = [
+        Box::pin(lock.read()),
+        Box::pin(lock.read()),
+        Box::pin(lock.read()),
+    ];
+    let mut second_writer = Box::pin(lock.write());
+    let mut last_reader = Box::pin(lock.read());
+    for future in futures.iter_mut() {
+        assert!(poll_once(future).is_pending());
+    }
+    assert!(poll_once(&mut second_writer).is_pending());
+    assert!(poll_once(&mut last_reader).is_pending());
+    // The readers queued together get in together, the one after the writer waits.
+    drop(writer);
+    let ready: Vec<_> = futures
+        .iter_mut()
+        .map(|future| poll_once(future).is_ready())
+        .collect();
+    assert_eq!(ready, [true, true, true]);
+    assert_eq!(lock.reader_count(), 0);
+    assert!(poll_once(&mut second_writer).is_ready());
+    assert!(poll_once(&mut last_reader).is_ready());
+}
+
+#[test]
+fn upgrade_test() {
+    let lock = AsyncRwLock::new(0);
+    let upgradable = block_on(lock.upgradeable_read());
+    let reader = lock.try_read().unwrap();
+    let mut upgrade = Box::pin(upgradable.upgrade());
+    assert!(poll_once(&mut upgrade).is_pending());
+    // New readers wait for the upgrade.
+    assert!(lock.try_read().is_none());
+    let mut late_reader = Box::pin(lock.read());
+    assert!(poll_once(&mut late_reader).is_pending());
+    drop(reader);
+    let mut writer = match poll_once(&mut upgrade) {
+        Poll::Ready(writer) => writer,
+        Poll::Pending => panic!("the upgrade was not granted"),
+    };
+    *writer += 1;
+    let upgradable = writer.downgrade_to_upgradeable();
+    assert!(poll_once(&mut late_reader).is_ready());
+    assert_eq!(*upgradable, 1);
+}
+
+#[test]
+fn cancel_test() {
+    let lock = AsyncRwLock::new(());
+    let reader = lock.try_read().unwrap();
+    let mut writer = Box::pin(lock.write());
+    let mut upgrade = Box::pin(block_on(lock.upgradeable_read()).upgrade());
+    let mut late_reader = Box::pin(lock.read());
+    assert!(poll_once(&mut writer).is_pending());
+    assert!(poll_once(&mut upgrade).is_pending());
+    assert!(poll_once(&mut late_reader).is_pending());
+    // A writer handed the lock, and an upgrade still waiting, both give up.
+    drop(reader);
+    assert!(poll_once(&mut writer).is_pending());
+    drop(upgrade);
+    drop(writer);
+    assert!(poll_once(&mut late_reader).is_ready());
+    drop(late_reader);
+    assert!(lock.try_write().is_some());
+}
+
+async fn worker(lock: Arc<AsyncRwLock<(usize, usize)>>, id: usize, loop_cnt: usize) {
+    for i in 0..loop_cnt {
+        match (id + i) % 3 {
+            0 => {
+                let guard = lock.read().await;
+                assert_eq!(guard.0, guard.1);
+            }
+            1 => {
+                let mut guard = lock.write().await;
+                guard.0 += 1;
+                guard.1 += 1;
+            }
+            _ => {
+                let guard = lock.upgradeable_read().await;
+                assert_eq!(guard.0, guard.1);
+                let mut guard = guard.upgrade().await;
+                guard.0 += 1;
+                guard.1 += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn lots_and_lots() {
+    let lock = Arc::new(AsyncRwLock::new((0, 0)));
+    let thread_cnt = 4;
+    let task_cnt = 3;
+    let loop_cnt = 300;
+    let mut threads = vec![];
+    for t in 0..thread_cnt {
+        let lock = lock.clone();
+        threads.push(std::thread::spawn(move || {
+            let mut executor = Executor::new();
+            for i in 0..task_cnt {
+                executor.spawn(worker(lock.clone(), t * task_cnt + i, loop_cnt));
+            }
+            executor.run();
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    // Two of every three iterations write.
+    let writes = thread_cnt * task_cnt * loop_cnt * 2 / 3;
+    assert_eq!(*block_on(lock.read()), (writes, writes));
+}
@@ -0,0 +1,107 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use common::executor::{block_on, poll_once, yield_now, Executor};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+use lock::async_semaphore::AsyncSemaphore;
+
+#[test]
+fn try_acquire_test() {
+    let sem = AsyncSemaphore::new(3);
+    let a = sem.try_acquire(2).unwrap();
+    assert_eq!(a.num_permits(), 2);
+    assert!(sem.try_acquire(2).is_none());
+    let b = block_on(sem.acquire(1));
+    assert_eq!(sem.available_permits(), 0);
+    drop(a);
+    assert_eq!(sem.available_permits(), 2);
+    b.forget();
+    assert_eq!(sem.available_permits(), 2);
+    sem.release(1);
+    assert_eq!(sem.available_permits(), 3);
+}
+
+#[test]
+fn fifo_test() {
+    let sem = AsyncSemaphore::new(0);
+    let mut big = Box::pin(sem.acquire(2));
+    let mut small = Box::pin(sem.acquire(1));
+    assert!(poll_once(&mut big).is_pending());
+    assert!(poll_once(&mut small).is_pending());
+    // One permit would do for `small`, but it doesn't overtake `big`.
+    sem.release(1);
+    assert!(poll_once(&mut small).is_pending());
+    assert!(sem.try_acquire(1).is_none());
+    sem.release(1);
+    let big = match poll_once(&mut big) {
+        Poll::Ready(permit) => permit,
+        Poll::Pending => panic!("big should have its permits"),
+    };
+    assert!(poll_once(&mut small).is_pending());
+    drop(big);
+    assert!(poll_once(&mut small).is_ready());
+    assert_eq!(sem.available_permits(), 2);
+}
+
+#[test]
+fn cancel_test() {
+    let sem = AsyncSemaphore::new(0);
+    let mut first = Box::pin(sem.acquire(2));
+    let mut second = Box::pin(sem.acquire(1));
+    assert!(poll_once(&mut first).is_pending());
+    assert!(poll_once(&mut second).is_pending());
+    sem.release(2);
+    // `first` was handed both permits, and gives them back as it leaves.
+    drop(first);
+    assert_eq!(sem.available_permits(), 1);
+    assert!(poll_once(&mut second).is_ready());
+    drop(second);
+    assert_eq!(sem.available_permits(), 2);
+}
+
+#[test]
+fn limit_test() {
+    let sem = Arc::new(AsyncSemaphore::new(2));
+    let inside = Arc::new(AtomicUsize::new(0));
+    let max_inside = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    for _ in 0..8 {
+        let (sem, inside, max_inside) = (sem.clone(), inside.clone(), max_inside.clone());
+        executor.spawn(async move {
+            for _ in 0..100 {
+                let _permit = sem.acquire(1).await;
+                let now = inside.fetch_add(1, Ordering::Relaxed) + 1;
+                max_inside.fetch_max(now, Ordering::Relaxed);
+                // Let the other tasks run while we hold the permit.
+                yield_now().await;
+                inside.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+    }
+    executor.run();
+    assert_eq!(max_inside.load(Ordering::Relaxed), 2);
+    assert_eq!(sem.available_permits(), 2);
+}
+
+#[test]
+fn interrupt_release_test() {
+    // Another thread plays the interrupt handler handing out permits.
+    let sem = Arc::new(AsyncSemaphore::new(0));
+    let sem_clone = sem.clone();
+    let handler = std::thread::spawn(move || {
+        for _ in 0..100 {
+            sem_clone.release(1);
+        }
+    });
+    block_on(async {
+        for _ in 0..100 {
+            sem.acquire(1).await.forget();
+        }
+    });
+    handler.join().unwrap();
+    assert_eq!(sem.available_permits(), 0);
+}
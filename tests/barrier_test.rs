@@ -0,0 +1,59 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::barrier::Barrier;
+
+fn rendezvous_test(interruptible: bool) {
+    let thread_cnt = 4;
+    let round_cnt = 20;
+    let barrier = Arc::new(Barrier::new(thread_cnt));
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let leaders = Arc::new(AtomicUsize::new(0));
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let barrier_clone = barrier.clone();
+        let arrived_clone = arrived.clone();
+        let leaders_clone = leaders.clone();
+        threads.push(std::thread::spawn(move || {
+            for round in 1..=round_cnt {
+                arrived_clone.fetch_add(1, Ordering::SeqCst);
+                let result = if interruptible {
+                    barrier_clone.wait_interruptible()
+                } else {
+                    barrier_clone.wait()
+                };
+                // Nobody passes before everybody has arrived.
+                assert!(arrived_clone.load(Ordering::SeqCst) >= round * thread_cnt);
+                if result.is_leader() {
+                    leaders_clone.fetch_add(1, Ordering::SeqCst);
+                }
+                // Keep the next round's arrivals out of this round's check.
+                barrier_clone.wait();
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(arrived.load(Ordering::SeqCst), thread_cnt * round_cnt);
+    assert_eq!(leaders.load(Ordering::SeqCst), round_cnt);
+}
+
+#[test]
+fn barrier_test() {
+    rendezvous_test(false);
+}
+
+#[test]
+fn barrier_interruptible_test() {
+    rendezvous_test(true);
+}
+
+#[test]
+fn single_cpu_barrier_test() {
+    let barrier = Barrier::new(1);
+    for _ in 0..3 {
+        assert!(barrier.wait().is_leader());
+    }
+}
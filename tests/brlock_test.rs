@@ -0,0 +1,56 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use lock::brlock::BrLock;
+
+#[test]
+fn basic_test() {
+    let x = Arc::new(BrLock::new(0));
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let x_clone = x.clone();
+        threads.push(std::thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                let before = *x_clone.read();
+                *x_clone.write() += 1;
+                assert!(*x_clone.read() > before);
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*(x.read()), thread_cnt * loop_cnt);
+}
+
+#[test]
+fn try_test() {
+    let x = Arc::new(BrLock::new(0));
+    let read = x.read();
+    // Reads nest on the same cpu.
+    assert!(x.try_read().is_some());
+
+    let x_clone = x.clone();
+    let other = std::thread::spawn(move || x_clone.try_write().is_none());
+    assert!(other.join().unwrap());
+
+    drop(read);
+    let write = x.try_write();
+    assert!(write.is_some());
+
+    let x_clone = x.clone();
+    let other = std::thread::spawn(move || x_clone.try_read().is_none());
+    assert!(other.join().unwrap());
+}
+
+#[test]
+fn slot_reuse_test() {
+    // Far more locks than reader slots, as long as they are not alive at once.
+    for i in 0..1000 {
+        let x = BrLock::new(i);
+        assert_eq!(*x.read(), i);
+        assert_eq!(x.into_inner(), i);
+    }
+}
@@ -0,0 +1,77 @@
+
+use core::cell::RefCell;
+use lock::ceiling::{current_priority, register_priority_hook, CeilingMutex};
+
+thread_local! {
+    static LEVELS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+fn log_level(level: usize) {
+    LEVELS.with(|levels| levels.borrow_mut().push(level));
+}
+
+fn take_levels() -> Vec<usize> {
+    LEVELS.with(|levels| levels.borrow_mut().split_off(0))
+}
+
+#[test]
+fn raise_restore_test() {
+    register_priority_hook(log_level);
+    take_levels();
+    let low: CeilingMutex<i32, 3> = CeilingMutex::new(0);
+    let high: CeilingMutex<i32, 5> = CeilingMutex::new(0);
+    {
+        let mut low_guard = low.lock();
+        assert_eq!(current_priority(), 3);
+        {
+            let mut high_guard = high.lock();
+            assert_eq!(current_priority(), 5);
+            *high_guard += 1;
+        }
+        assert_eq!(current_priority(), 3);
+        *low_guard += 1;
+    }
+    assert_eq!(current_priority(), 0);
+    assert_eq!(take_levels(), [3, 5, 3, 0]);
+
+    // Locking at the ceiling itself doesn't change the level.
+    let same: CeilingMutex<(), 5> = CeilingMutex::new(());
+    {
+        let _high_guard = high.lock();
+        let _same_guard = same.lock();
+        assert!(high.try_lock().is_none());
+        assert_eq!(current_priority(), 5);
+    }
+    assert_eq!(take_levels(), [5, 0]);
+    assert_eq!(low.into_inner() + high.into_inner(), 2);
+}
+
+#[test]
+#[should_panic(expected = "above its ceiling")]
+fn above_ceiling_test() {
+    let low: CeilingMutex<(), 3> = CeilingMutex::new(());
+    let high: CeilingMutex<(), 5> = CeilingMutex::new(());
+    let _high_guard = high.lock();
+    let _low_guard = low.lock();
+}
+
+#[test]
+fn lots_and_lots() {
+    static M: CeilingMutex<u32, 7> = CeilingMutex::new(0);
+    let thread_cnt = 4;
+    let iter_cnt = 1000;
+    let handles: Vec<_> = (0..thread_cnt)
+        .map(|_| {
+            std::thread::spawn(move || {
+                for _ in 0..iter_cnt {
+                    *M.lock() += 1;
+                    assert_eq!(current_priority(), 0);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*M.lock(), thread_cnt * iter_cnt);
+}
@@ -0,0 +1,60 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use lock::cohort::CohortMutex;
+use lock::topology::register_topology;
+
+// Fake dual-socket topology: even cpus on node 0, odd cpus on node 1.
+fn fake_topology() {
+    register_topology(&[0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1]);
+}
+
+fn count_with(max_handoffs: usize) {
+    fake_topology();
+    let x = Arc::new(CohortMutex::with_max_handoffs(0, max_handoffs));
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let x_clone = x.clone();
+        threads.push(std::thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                let mut guard = x_clone.lock();
+                *guard += 1;
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*(x.lock()), thread_cnt * loop_cnt);
+}
+
+#[test]
+fn basic_test() {
+    count_with(lock::cohort::DEFAULT_MAX_HANDOFFS);
+}
+
+#[test]
+fn no_handoff_test() {
+    count_with(0);
+}
+
+#[test]
+fn try_lock_test() {
+    fake_topology();
+    let x = Arc::new(CohortMutex::new(0));
+    let lock_result0 = x.try_lock();
+    assert!(lock_result0.is_some());
+    assert!(x.is_locked());
+
+    let x_clone = x.clone();
+    let other = std::thread::spawn(move || x_clone.try_lock().is_none());
+    assert!(other.join().unwrap());
+
+    drop(lock_result0);
+    assert!(!x.is_locked());
+
+    let lock_result1 = x.try_lock();
+    assert!(lock_result1.is_some());
+}
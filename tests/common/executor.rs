@@ -0,0 +1,138 @@
+//! A minimal executor for hosted tests of the async primitives.
+
+use lock::spin::SpinMutex;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            // A wakeup since the poll leaves a token, so it is not lost.
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Polls `future` once with a waker that does nothing.
+pub fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    Pin::new(future).poll(&mut Context::from_waker(&waker))
+}
+
+/// Lets the other tasks run before coming back.
+pub fn yield_now() -> YieldNow {
+    YieldNow(false)
+}
+
+pub struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+struct ReadyQueue {
+    ready: SpinMutex<Vec<usize>>,
+    thread: Thread,
+}
+
+struct TaskWaker {
+    task: usize,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.queue.ready.lock().push(self.task);
+        self.queue.thread.unpark();
+    }
+}
+
+type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs many tasks on the current thread, polling each when it is woken.
+pub struct Executor {
+    tasks: Vec<Option<Task>>,
+    queue: Arc<ReadyQueue>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            tasks: Vec::new(),
+            queue: Arc::new(ReadyQueue {
+                ready: SpinMutex::new(Vec::new()),
+                thread: thread::current(),
+            }),
+        }
+    }
+
+    /// Adds a task, to be first polled in spawning order.
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + Send + 'static) {
+        self.queue.ready.lock().push(self.tasks.len());
+        self.tasks.push(Some(Box::pin(future)));
+    }
+
+    /// Polls the tasks in the order they are woken until all of them finish.
+    pub fn run(&mut self) {
+        while !self.run_until_stalled() {
+            thread::park();
+        }
+    }
+
+    /// Polls the tasks in the order they are woken until none is ready,
+    /// returning whether all of them finished.
+    pub fn run_until_stalled(&mut self) -> bool {
+        loop {
+            let ready: Vec<usize> = self.queue.ready.lock().drain(..).collect();
+            if ready.is_empty() {
+                return self.tasks.iter().all(Option::is_none);
+            }
+            for task in ready {
+                if let Some(future) = self.tasks[task].as_mut() {
+                    let waker = Waker::from(Arc::new(TaskWaker {
+                        task,
+                        queue: self.queue.clone(),
+                    }));
+                    if future
+                        .as_mut()
+                        .poll(&mut Context::from_waker(&waker))
+                        .is_ready()
+                    {
+                        self.tasks[task] = None;
+                    }
+                }
+            }
+        }
+    }
+}
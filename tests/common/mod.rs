@@ -0,0 +1,131 @@
+//! A `Scheduler` for hosted tests, where every std thread is a task.
+
+#![allow(dead_code)]
+
+pub mod executor;
+
+use lock::sched::{PriorityScheduler, Scheduler};
+use lock::spin::SpinMutex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+static SLEEPERS: SpinMutex<Option<HashMap<usize, Vec<Thread>>>> = SpinMutex::new(None);
+
+pub struct StdScheduler;
+
+static NEXT_TASK: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static TASK: usize = NEXT_TASK.fetch_add(1, Ordering::Relaxed);
+}
+
+impl StdScheduler {
+    fn enqueue(key: usize, should_block: &mut dyn FnMut() -> bool) -> bool {
+        let mut sleepers = SLEEPERS.lock();
+        if !should_block() {
+            return false;
+        }
+        sleepers
+            .get_or_insert_with(HashMap::new)
+            .entry(key)
+            .or_default()
+            .push(thread::current());
+        true
+    }
+
+    // Leaves the queue if we woke up spuriously or timed out.
+    fn dequeue(key: usize) {
+        let me = thread::current().id();
+        if let Some(queue) = SLEEPERS.lock().as_mut().and_then(|s| s.get_mut(&key)) {
+            queue.retain(|thread| thread.id() != me);
+        }
+    }
+}
+
+impl Scheduler for StdScheduler {
+    fn current_task() -> usize {
+        TASK.with(|task| *task)
+    }
+
+    fn block(key: usize, should_block: &mut dyn FnMut() -> bool) {
+        if Self::enqueue(key, should_block) {
+            // A wakeup sent since we unlocked leaves a token, so it is not lost.
+            thread::park();
+            Self::dequeue(key);
+        }
+    }
+
+    fn block_timeout(
+        key: usize,
+        should_block: &mut dyn FnMut() -> bool,
+        timeout: Duration,
+    ) -> Duration {
+        let start = Instant::now();
+        if !Self::enqueue(key, should_block) {
+            return timeout;
+        }
+        thread::park_timeout(timeout);
+        Self::dequeue(key);
+        timeout.saturating_sub(start.elapsed())
+    }
+
+    fn wake(key: usize, n: usize) -> usize {
+        let woken: Vec<Thread> = {
+            let mut sleepers = SLEEPERS.lock();
+            match sleepers.as_mut().and_then(|s| s.get_mut(&key)) {
+                Some(queue) => {
+                    let n = n.min(queue.len());
+                    queue.drain(..n).collect()
+                }
+                None => Vec::new(),
+            }
+        };
+        for thread in woken.iter() {
+            thread.unpark();
+        }
+        woken.len()
+    }
+}
+
+struct Priorities {
+    base: HashMap<usize, usize>,
+    log: Vec<(usize, usize)>, // Every `set_priority` call, in order.
+}
+
+static PRIORITIES: SpinMutex<Option<Priorities>> = SpinMutex::new(None);
+
+fn with_priorities<R>(f: impl FnOnce(&mut Priorities) -> R) -> R {
+    f(PRIORITIES.lock().get_or_insert_with(|| Priorities {
+        base: HashMap::new(),
+        log: Vec::new(),
+    }))
+}
+
+/// Sets the base priority of the current task.
+pub fn set_base_priority(priority: usize) {
+    let task = StdScheduler::current_task();
+    with_priorities(|p| p.base.insert(task, priority));
+}
+
+/// Returns the priorities `task` has been set to, in order.
+pub fn priority_log(task: usize) -> Vec<usize> {
+    with_priorities(|p| {
+        p.log
+            .iter()
+            .filter(|(t, _)| *t == task)
+            .map(|(_, priority)| *priority)
+            .collect()
+    })
+}
+
+impl PriorityScheduler for StdScheduler {
+    fn base_priority(task: usize) -> usize {
+        with_priorities(|p| p.base.get(&task).copied().unwrap_or(0))
+    }
+
+    fn set_priority(task: usize, priority: usize) {
+        with_priorities(|p| p.log.push((task, priority)));
+    }
+}
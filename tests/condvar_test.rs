@@ -0,0 +1,89 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::StdScheduler;
+use lock::condvar::Condvar;
+use lock::rwlock::RwLock;
+use lock::sched::Scheduler;
+use lock::spin::SpinMutex;
+use lock::ticket::TicketMutex;
+
+// A bounded handoff: producers wait for room, consumers for items.
+fn producer_consumer_test<S: Scheduler + Send + Sync + 'static>() {
+    let state = Arc::new((
+        SpinMutex::new(vec::Vec::new()),
+        Condvar::<S>::with_scheduler(),
+    ));
+    let item_cnt = 1000;
+    let state_clone = state.clone();
+    let producer = std::thread::spawn(move || {
+        let (queue, condvar) = &*state_clone;
+        for i in 0..item_cnt {
+            let mut guard = condvar.wait_while(queue.lock(), |queue| queue.len() >= 4);
+            guard.push(i);
+            drop(guard);
+            condvar.notify_all();
+        }
+    });
+    let (queue, condvar) = &*state;
+    for i in 0..item_cnt {
+        let mut guard = condvar.wait_while(queue.lock(), |queue| queue.is_empty());
+        assert_eq!(guard.remove(0), i);
+        drop(guard);
+        condvar.notify_all();
+    }
+    producer.join().unwrap();
+}
+
+#[test]
+fn spin_condvar_test() {
+    producer_consumer_test::<lock::sched::SpinWait>();
+}
+
+#[test]
+fn blocking_condvar_test() {
+    producer_consumer_test::<StdScheduler>();
+}
+
+#[test]
+fn ticket_condvar_test() {
+    let state = Arc::new((TicketMutex::new(false), Condvar::new()));
+    let state_clone = state.clone();
+    let waiter = std::thread::spawn(move || {
+        let (flag, condvar) = &*state_clone;
+        let guard = condvar.wait_while(flag.lock(), |flag| !*flag);
+        assert!(*guard);
+    });
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let (flag, condvar) = &*state;
+    *flag.lock() = true;
+    condvar.notify_one();
+    waiter.join().unwrap();
+}
+
+#[test]
+fn rwlock_condvar_test() {
+    let state = Arc::new((
+        RwLock::new(0usize),
+        Condvar::<StdScheduler>::with_scheduler(),
+    ));
+    let mut waiters = vec![];
+    for _ in 0..3 {
+        let state_clone = state.clone();
+        waiters.push(std::thread::spawn(move || {
+            let (count, condvar) = &*state_clone;
+            let mut guard = condvar.wait_while(count.write(), |count| *count == 0);
+            *guard += 1;
+        }));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let (count, condvar) = &*state;
+    *count.write() = 1;
+    condvar.notify_all();
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert_eq!(*count.read(), 4);
+}
@@ -0,0 +1,141 @@
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use lock::epoch::{self, Guard};
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct Node {
+    value: usize,
+    next: *mut Node,
+}
+
+unsafe impl Send for Node {}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+// A Treiber stack, the classic user of deferred reclamation.
+struct Stack {
+    head: AtomicPtr<Node>,
+}
+
+unsafe impl Sync for Stack {}
+unsafe impl Send for Stack {}
+
+impl Stack {
+    fn push(&self, value: usize) {
+        let node = Box::into_raw(Box::new(Node {
+            value,
+            next: ptr::null_mut(),
+        }));
+        let _guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            unsafe { (*node).next = head };
+            if self
+                .head
+                .compare_exchange(head, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    fn pop(&self, guard: &Guard) -> Option<usize> {
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            if head.is_null() {
+                return None;
+            }
+            // Safe to read even if another cpu pops it meanwhile, as we are pinned.
+            let (value, next) = unsafe { ((*head).value, (*head).next) };
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                unsafe { guard.defer_destroy(head) };
+                return Some(value);
+            }
+        }
+    }
+}
+
+#[test]
+fn epoch_stack_test() {
+    let stack = Arc::new(Stack {
+        head: AtomicPtr::new(ptr::null_mut()),
+    });
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let popped = Arc::new(AtomicUsize::new(0));
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let stack_clone = stack.clone();
+        let popped_clone = popped.clone();
+        threads.push(std::thread::spawn(move || {
+            for i in 0..loop_cnt {
+                stack_clone.push(i);
+                let guard = epoch::pin();
+                if stack_clone.pop(&guard).is_some() {
+                    popped_clone.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    let guard = epoch::pin();
+    while stack.pop(&guard).is_some() {
+        popped.fetch_add(1, Ordering::SeqCst);
+    }
+    drop(guard);
+    assert_eq!(popped.load(Ordering::SeqCst), thread_cnt * loop_cnt);
+
+    // Once nobody is pinned, the epoch advances and everything is freed.
+    while DROPPED.load(Ordering::SeqCst) < thread_cnt * loop_cnt {
+        epoch::collect();
+        std::thread::yield_now();
+    }
+    assert_eq!(DROPPED.load(Ordering::SeqCst), thread_cnt * loop_cnt);
+}
+
+#[test]
+fn pinned_blocks_reclamation_test() {
+    let guard = epoch::pin();
+    assert!(epoch::is_pinned());
+    let nested = epoch::pin();
+    drop(nested);
+    assert!(epoch::is_pinned());
+
+    let freed = Arc::new(AtomicUsize::new(0));
+    let freed_clone = freed.clone();
+    guard.defer(move || {
+        freed_clone.fetch_add(1, Ordering::SeqCst);
+    });
+    // Another cpu can advance the epoch at most once past our pin.
+    let helper = std::thread::spawn(|| {
+        for _ in 0..3 {
+            epoch::collect();
+        }
+    });
+    helper.join().unwrap();
+    assert_eq!(freed.load(Ordering::SeqCst), 0);
+    drop(guard);
+    assert!(!epoch::is_pinned());
+
+    while freed.load(Ordering::SeqCst) == 0 {
+        epoch::collect();
+        std::thread::yield_now();
+    }
+    assert_eq!(freed.load(Ordering::SeqCst), 1);
+}
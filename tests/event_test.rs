@@ -0,0 +1,100 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use common::executor::{block_on, poll_once};
+use lock::event::Event;
+
+#[test]
+fn manual_reset_test() {
+    let event = Event::manual_reset(false);
+    assert!(!event.is_auto_reset());
+    let mut a = Box::pin(event.wait());
+    let mut b = Box::pin(event.wait());
+    assert!(poll_once(&mut a).is_pending());
+    assert!(poll_once(&mut b).is_pending());
+    event.set();
+    assert!(poll_once(&mut a).is_ready());
+    assert!(poll_once(&mut b).is_ready());
+    // It stays set until it is reset.
+    assert!(event.is_set());
+    block_on(event.wait());
+    event.reset();
+    assert!(!event.is_set());
+    assert!(poll_once(&mut Box::pin(event.wait())).is_pending());
+}
+
+#[test]
+fn auto_reset_test() {
+    let event = Event::auto_reset(true);
+    assert!(event.is_auto_reset());
+    // Set with nobody waiting, it lets the next one through.
+    block_on(event.wait());
+    assert!(!event.is_set());
+    let mut a = Box::pin(event.wait());
+    let mut b = Box::pin(event.wait());
+    assert!(poll_once(&mut a).is_pending());
+    assert!(poll_once(&mut b).is_pending());
+    event.set();
+    assert!(!event.is_set());
+    assert!(poll_once(&mut b).is_pending());
+    assert!(poll_once(&mut a).is_ready());
+    event.set();
+    assert!(poll_once(&mut b).is_ready());
+    assert!(!event.is_set());
+}
+
+#[test]
+fn woken_then_reset_test() {
+    let event = Event::manual_reset(false);
+    let mut a = Box::pin(event.wait());
+    assert!(poll_once(&mut a).is_pending());
+    event.set();
+    event.reset();
+    // `a` was woken by the `set`, so it completes anyway.
+    assert!(poll_once(&mut a).is_ready());
+    assert!(poll_once(&mut Box::pin(event.wait())).is_pending());
+}
+
+#[test]
+fn cancel_test() {
+    let event = Event::auto_reset(false);
+    let mut a = Box::pin(event.wait());
+    let mut b = Box::pin(event.wait());
+    assert!(poll_once(&mut a).is_pending());
+    assert!(poll_once(&mut b).is_pending());
+    event.set();
+    // `a` was picked, and passes the wakeup on as it leaves.
+    drop(a);
+    assert!(poll_once(&mut b).is_ready());
+    let mut c = Box::pin(event.wait());
+    assert!(poll_once(&mut c).is_pending());
+    event.set();
+    drop(c);
+    // With nobody left to pass it to, the event is set again.
+    assert!(event.is_set());
+}
+
+#[test]
+fn ping_pong_test() {
+    // Another thread plays the interrupt handler answering each request.
+    let request = Arc::new(Event::auto_reset(false));
+    let reply = Arc::new(Event::auto_reset(false));
+    let (request_clone, reply_clone) = (request.clone(), reply.clone());
+    let handler = std::thread::spawn(move || {
+        block_on(async {
+            for _ in 0..100 {
+                request_clone.wait().await;
+                reply_clone.set();
+            }
+        })
+    });
+    block_on(async {
+        for _ in 0..100 {
+            request.set();
+            reply.wait().await;
+        }
+    });
+    handler.join().unwrap();
+    assert!(!request.is_set() && !reply.is_set());
+}
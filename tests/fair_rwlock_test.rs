@@ -0,0 +1,82 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lock::fair_rwlock::FairRwLock;
+
+#[test]
+fn basic_test() {
+    let x = Arc::new(FairRwLock::new(0));
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let mut threads = vec![];
+    for i in 0..thread_cnt {
+        let x_clone = x.clone();
+        threads.push(std::thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                if i % 2 == 0 {
+                    *x_clone.write() += 1;
+                } else {
+                    let guard = x_clone.upgradeable_read();
+                    let mut guard = guard.upgrade();
+                    *guard += 1;
+                }
+                assert!(*x_clone.read() > 0);
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*(x.read()), thread_cnt * loop_cnt);
+}
+
+#[test]
+fn try_test() {
+    let x = FairRwLock::new(0);
+    let read0 = x.try_read();
+    assert!(read0.is_some());
+    assert!(x.try_read().is_some());
+    assert!(x.try_write().is_none());
+
+    let upgradeable = x.try_upgradeable_read().unwrap();
+    assert!(x.try_upgradeable_read().is_none());
+    // Readers may still enter next to an upgradeable guard.
+    assert!(x.try_read().is_some());
+    let upgradeable = upgradeable.try_upgrade().unwrap_err();
+    drop(read0);
+    let write = upgradeable.try_upgrade().unwrap();
+    assert!(x.try_read().is_none());
+
+    let read = write.downgrade();
+    assert!(x.try_read().is_some());
+    assert!(x.try_write().is_none());
+    drop(read);
+    assert!(x.try_write().is_some());
+}
+
+#[test]
+fn writer_not_starved_test() {
+    let x = Arc::new(FairRwLock::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..3 {
+        let x_clone = x.clone();
+        let stop_clone = stop.clone();
+        readers.push(std::thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                let guard = x_clone.read();
+                assert!(*guard <= 100);
+            }
+        }));
+    }
+    // Every write must get through the read storm.
+    for _ in 0..100 {
+        *x.write() += 1;
+    }
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(*x.read(), 100);
+}
@@ -0,0 +1,207 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::StdScheduler;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use lock::futex::{
+    futex_requeue, futex_wait, futex_wait_timeout, futex_wake, futex_wake_op, FutexCmp, FutexError,
+    FutexOp, FutexOpKind,
+};
+use lock::sched::Scheduler;
+use std::time::Duration;
+
+type S = StdScheduler;
+
+#[test]
+fn wait_mismatch_test() {
+    let futex = AtomicU32::new(1);
+    assert_eq!(futex_wait::<S>(&futex, 0), Err(FutexError::WouldBlock));
+    assert_eq!(
+        futex_wait_timeout::<S>(&futex, 1, Duration::from_millis(10)),
+        Err(FutexError::TimedOut)
+    );
+    assert_eq!(futex_wake::<S>(&futex, 1), 0);
+}
+
+// A mutex in the style of Drepper's "Futexes Are Tricky":
+// 0 unlocked, 1 locked, 2 locked with waiters.
+struct FutexMutex(AtomicU32);
+
+impl FutexMutex {
+    fn lock(&self) {
+        if self
+            .0
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+        while self.0.swap(2, Ordering::Acquire) != 0 {
+            let _ = futex_wait::<S>(&self.0, 2);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.0.swap(0, Ordering::Release) == 2 {
+            futex_wake::<S>(&self.0, 1);
+        }
+    }
+}
+
+#[test]
+fn futex_mutex_test() {
+    let mutex = Arc::new(FutexMutex(AtomicU32::new(0)));
+    let count = Arc::new(AtomicU32::new(0));
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let mutex = mutex.clone();
+        let count = count.clone();
+        threads.push(std::thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                mutex.lock();
+                // Not an atomic increment, the lock makes it safe.
+                count.store(count.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+                mutex.unlock();
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(count.load(Ordering::Relaxed), thread_cnt * loop_cnt);
+}
+
+#[test]
+fn requeue_test() {
+    let futexes = Arc::new([AtomicU32::new(0), AtomicU32::new(0)]);
+    let mut threads = vec![];
+    for _ in 0..3 {
+        let futexes = futexes.clone();
+        threads.push(std::thread::spawn(move || futex_wait::<S>(&futexes[0], 0)));
+    }
+    // Move every waiter over, however late it queued itself.
+    let mut moved = 0;
+    while moved < 3 {
+        moved += futex_requeue::<S>(&futexes[0], 0, &futexes[1], usize::MAX, 0).unwrap();
+        std::thread::yield_now();
+    }
+    assert_eq!(
+        futex_requeue::<S>(&futexes[0], 0, &futexes[1], usize::MAX, 1),
+        Err(FutexError::WouldBlock)
+    );
+    assert_eq!(futex_wake::<S>(&futexes[0], usize::MAX), 0);
+    assert_eq!(futex_wake::<S>(&futexes[1], 1), 1);
+    assert_eq!(futex_wake::<S>(&futexes[1], usize::MAX), 2);
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), Ok(()));
+    }
+}
+
+#[test]
+fn requeue_timeout_test() {
+    // A waiter times out of the futex it has been requeued to.
+    let futexes = Arc::new([AtomicU32::new(0), AtomicU32::new(0)]);
+    let futexes_clone = futexes.clone();
+    let done = Arc::new(AtomicBool::new(false));
+    let done_clone = done.clone();
+    let waiter = std::thread::spawn(move || {
+        let result = futex_wait_timeout::<S>(&futexes_clone[0], 0, Duration::from_millis(200));
+        done_clone.store(true, Ordering::Relaxed);
+        result
+    });
+    while !done.load(Ordering::Relaxed)
+        && futex_requeue::<S>(&futexes[0], 0, &futexes[1], 1, 0).unwrap() == 0
+    {
+        std::thread::yield_now();
+    }
+    assert_eq!(waiter.join().unwrap(), Err(FutexError::TimedOut));
+    assert_eq!(futex_wake::<S>(&futexes[1], usize::MAX), 0);
+}
+
+#[test]
+fn wake_op_test() {
+    let futexes = Arc::new([AtomicU32::new(0), AtomicU32::new(5)]);
+    let mut threads = vec![];
+    for i in 0..2 {
+        let futexes = futexes.clone();
+        let expected = futexes[i].load(Ordering::Relaxed);
+        threads.push(std::thread::spawn(move || {
+            futex_wait::<S>(&futexes[i], expected)
+        }));
+    }
+    // Old value 5 is not below 5: only the waiter on the first futex wakes.
+    let op = FutexOp::new(FutexOpKind::Add, 1, FutexCmp::Lt, 5);
+    let mut woken = 0;
+    while woken == 0 {
+        woken = futex_wake_op::<S>(&futexes[0], 1, &futexes[1], 1, op);
+        std::thread::yield_now();
+    }
+    assert_eq!(woken, 1);
+    assert_eq!(threads.remove(0).join().unwrap(), Ok(()));
+    // The word moved on, so the second waiter is either asleep or gone.
+    let op = FutexOp::new(FutexOpKind::Set, 0, FutexCmp::Gt, 5);
+    let woken = futex_wake_op::<S>(&futexes[0], 1, &futexes[1], 1, op);
+    let result = threads.remove(0).join().unwrap();
+    assert_eq!(woken == 1, result.is_ok());
+    assert_eq!(futexes[1].load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn op_decode_test() {
+    // FUTEX_OP(FUTEX_OP_ADD, 1, FUTEX_OP_CMP_GT, -1)
+    let bits = (1 << 28) | (4 << 24) | (1 << 12) | 0xfff;
+    assert_eq!(
+        FutexOp::from_bits(bits),
+        Some(FutexOp::new(FutexOpKind::Add, 1, FutexCmp::Gt, u32::MAX))
+    );
+    // FUTEX_OP(FUTEX_OP_OR | FUTEX_OP_OPARG_SHIFT, 4, FUTEX_OP_CMP_EQ, 0)
+    let bits = ((8 | 2) << 28) | (4 << 12);
+    assert_eq!(
+        FutexOp::from_bits(bits),
+        Some(FutexOp::new(FutexOpKind::Or, 16, FutexCmp::Eq, 0))
+    );
+    assert_eq!(FutexOp::from_bits(7 << 28), None);
+}
+
+// Wakes nobody: a waiter returns as soon as it sees the wakeup.
+struct YieldWait;
+
+impl Scheduler for YieldWait {
+    fn current_task() -> usize {
+        0
+    }
+
+    fn block(_key: usize, should_block: &mut dyn FnMut() -> bool) {
+        if should_block() {
+            std::thread::yield_now();
+        }
+    }
+
+    fn wake(_key: usize, _n: usize) -> usize {
+        0
+    }
+}
+
+#[test]
+fn waiter_leaves_on_wakeup_test() {
+    // Every waiter is gone from its stack right after the waker marks it
+    // woken, so the waker must not touch it afterwards.
+    let rounds = 1000;
+    let futex = Arc::new(AtomicU32::new(0));
+    let futex_clone = futex.clone();
+    let waiter = std::thread::spawn(move || {
+        for _ in 0..rounds {
+            futex_wait::<YieldWait>(&futex_clone, 0).unwrap();
+        }
+    });
+    for _ in 0..rounds {
+        while futex_wake::<YieldWait>(&futex, 1) == 0 {
+            std::thread::yield_now();
+        }
+    }
+    waiter.join().unwrap();
+}
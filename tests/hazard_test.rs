@@ -0,0 +1,110 @@
+extern crate alloc;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use lock::hazard::HazardDomain;
+
+struct Node {
+    value: usize,
+    alive: AtomicBool,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl Node {
+    fn new(value: usize, dropped: &Arc<AtomicUsize>) -> *mut Node {
+        Box::into_raw(Box::new(Node {
+            value,
+            alive: AtomicBool::new(true),
+            dropped: dropped.clone(),
+        }))
+    }
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.alive.store(false, Ordering::SeqCst);
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn protect_blocks_free_test() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let domain = HazardDomain::with_threshold(1);
+    let src = AtomicPtr::new(Node::new(1, &dropped));
+
+    let (hazard, ptr) = domain.protect(&src);
+    src.store(Node::new(2, &dropped), Ordering::SeqCst);
+    unsafe { domain.retire(ptr) };
+    // Still protected, so the scan kept it.
+    assert_eq!(domain.retired_count(), 1);
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+    assert_eq!(unsafe { (*ptr).value }, 1);
+
+    hazard.reset();
+    domain.scan();
+    assert_eq!(domain.retired_count(), 0);
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+    drop(hazard);
+
+    // Nested hazard pointers take different slots.
+    let first = domain.hazard();
+    let second = domain.hazard();
+    let protected = first.protect(&src);
+    assert_eq!(second.protect(&src), protected);
+    drop(second);
+    drop(first);
+
+    unsafe { domain.retire(src.load(Ordering::SeqCst)) };
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn concurrent_protect_retire_test() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let domain = Arc::new(HazardDomain::new());
+    let src = Arc::new(AtomicPtr::new(Node::new(0, &dropped)));
+    let done = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..3 {
+        let domain_clone = domain.clone();
+        let src_clone = src.clone();
+        let done_clone = done.clone();
+        readers.push(std::thread::spawn(move || {
+            while !done_clone.load(Ordering::SeqCst) {
+                let (hazard, ptr) = domain_clone.protect(&*src_clone);
+                let node = unsafe { &*ptr };
+                assert!(node.alive.load(Ordering::SeqCst));
+                std::thread::yield_now();
+                assert!(node.alive.load(Ordering::SeqCst));
+                drop(hazard);
+            }
+        }));
+    }
+    let writer_cnt = 2;
+    let loop_cnt = 1000;
+    let mut writers = vec![];
+    for _ in 0..writer_cnt {
+        let domain_clone = domain.clone();
+        let src_clone = src.clone();
+        let dropped_clone = dropped.clone();
+        writers.push(std::thread::spawn(move || {
+            for i in 1..=loop_cnt {
+                let old = src_clone.swap(Node::new(i, &dropped_clone), Ordering::SeqCst);
+                unsafe { domain_clone.retire(old) };
+            }
+        }));
+    }
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    domain.scan();
+    assert_eq!(domain.retired_count(), 0);
+    assert_eq!(dropped.load(Ordering::SeqCst), writer_cnt * loop_cnt);
+    unsafe { drop(Box::from_raw(src.load(Ordering::SeqCst))) };
+}
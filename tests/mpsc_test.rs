@@ -0,0 +1,165 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use common::executor::{block_on, poll_once, Executor};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::mpsc::{self, SendError, TryRecvError, TrySendError};
+
+#[test]
+fn try_send_test() {
+    let (tx, mut rx) = mpsc::channel(2);
+    assert_eq!(tx.capacity(), 2);
+    tx.try_send(1).unwrap();
+    tx.try_send(2).unwrap();
+    assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+    assert_eq!(rx.len(), 2);
+    assert_eq!(rx.try_recv(), Ok(1));
+    tx.try_send(3).unwrap();
+    assert_eq!(block_on(rx.recv()), Some(2));
+    assert_eq!(block_on(rx.recv()), Some(3));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    assert!(rx.is_empty());
+}
+
+#[test]
+fn recv_test() {
+    let (tx, mut rx) = mpsc::channel(4);
+    let tx2 = tx.clone();
+    {
+        let mut recv = Box::pin(rx.recv());
+        assert!(poll_once(&mut recv).is_pending());
+        tx2.try_send(1).unwrap();
+        assert_eq!(poll_once(&mut recv), core::task::Poll::Ready(Some(1)));
+    }
+    tx.try_send(2).unwrap();
+    drop(tx);
+    drop(tx2);
+    // The values queued are still received after the senders are gone.
+    assert_eq!(block_on(rx.recv()), Some(2));
+    assert_eq!(block_on(rx.recv()), None);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+}
+
+#[test]
+fn closed_test() {
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.try_send(1).unwrap();
+    let mut send = Box::pin(tx.send(2));
+    assert!(poll_once(&mut send).is_pending());
+    rx.close();
+    assert!(tx.is_closed());
+    assert_eq!(
+        poll_once(&mut send),
+        core::task::Poll::Ready(Err(SendError(2)))
+    );
+    assert_eq!(tx.try_send(3), Err(TrySendError::Closed(3)));
+    assert_eq!(rx.try_recv(), Ok(1));
+    // Drained, nothing more can come even though `tx` is alive.
+    assert_eq!(block_on(rx.recv()), None);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    drop(rx);
+    assert_eq!(block_on(tx.send(4)), Err(SendError(4)));
+}
+
+#[test]
+fn send_fifo_test() {
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.try_send(0).unwrap();
+    let mut first = Box::pin(tx.send(1));
+    let mut second = Box::pin(tx.send(2));
+    assert!(poll_once(&mut first).is_pending());
+    assert!(poll_once(&mut second).is_pending());
+    assert_eq!(rx.try_recv(), Ok(0));
+    // The freed slot is kept for `first`, even from `try_send`.
+    assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+    assert!(poll_once(&mut second).is_pending());
+    assert!(poll_once(&mut first).is_ready());
+    assert_eq!(rx.try_recv(), Ok(1));
+    assert!(poll_once(&mut second).is_ready());
+    assert_eq!(rx.try_recv(), Ok(2));
+}
+
+#[test]
+fn cancel_test() {
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.try_send(0).unwrap();
+    let mut first = Box::pin(tx.send(1));
+    let mut second = Box::pin(tx.send(2));
+    assert!(poll_once(&mut first).is_pending());
+    assert!(poll_once(&mut second).is_pending());
+    assert_eq!(rx.try_recv(), Ok(0));
+    // `first` was kept a slot, and passes it on as it leaves.
+    drop(first);
+    assert!(poll_once(&mut second).is_ready());
+    assert_eq!(rx.try_recv(), Ok(2));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+}
+
+#[test]
+fn dropped_receiver_test() {
+    let value = Arc::new(());
+    let (tx, rx) = mpsc::channel(2);
+    tx.try_send(value.clone()).unwrap();
+    assert_eq!(Arc::strong_count(&value), 2);
+    drop(rx);
+    // The values still queued are dropped with the receiver.
+    assert_eq!(Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn interrupt_test() {
+    // Other threads play interrupt handlers, dropping what doesn't fit.
+    let (tx, mut rx) = mpsc::channel(8);
+    let sent = Arc::new(AtomicUsize::new(0));
+    let handlers: Vec<_> = (0..4)
+        .map(|_| {
+            let (tx, sent) = (tx.clone(), sent.clone());
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    if tx.try_send(i).is_ok() {
+                        sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(tx);
+    let received = block_on(async {
+        let mut received = 0;
+        while rx.recv().await.is_some() {
+            received += 1;
+        }
+        received
+    });
+    for handler in handlers {
+        handler.join().unwrap();
+    }
+    assert_eq!(received, sent.load(Ordering::Relaxed));
+}
+
+#[test]
+fn tasks_test() {
+    let (tx, mut rx) = mpsc::channel(2);
+    let sum = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    for task in 0..4 {
+        let tx = tx.clone();
+        executor.spawn(async move {
+            for i in 0..100 {
+                tx.send(task * 100 + i).await.unwrap();
+            }
+        });
+    }
+    drop(tx);
+    {
+        let sum = sum.clone();
+        executor.spawn(async move {
+            while let Some(value) = rx.recv().await {
+                sum.fetch_add(value, Ordering::Relaxed);
+            }
+        });
+    }
+    executor.run();
+    assert_eq!(sum.load(Ordering::Relaxed), (0..400).sum());
+}
@@ -1,28 +1,98 @@
-// extern crate alloc;
-// use alloc::sync::Arc;
-// use alloc::vec;
-// use kernel_sync::mutex::Mutex;
+extern crate alloc;
+mod common;
 
-// use tokio;
-// async fn handle(x: Arc<Mutex<i32>>, loop_cnt: i32) {
-//     for _ in 0..loop_cnt {
-//         let mut guard = x.lock().await;
-//         *guard += 1;
-//     }
-// }
+use alloc::sync::Arc;
+use alloc::vec;
+use common::executor::{block_on, poll_once, Executor};
+use lock::async_mutex::AsyncMutex as Mutex;
+use lock::spin::SpinMutex;
 
-// #[tokio::test]
-// async fn mutex_test() {
-//     let x = Arc::new(Mutex::new(0));
-//     let coroutine_cnt = 10;
-//     let loop_cnt = 500;
-//     let mut coroutines = vec![];
-//     for _ in 0..coroutine_cnt {
-//         let x_cloned = x.clone();
-//         coroutines.push(tokio::spawn(handle(x_cloned, loop_cnt)));
-//     }
-//     for coroutine in coroutines {
-//         tokio::join!(coroutine).0.unwrap();
-//     }
-//     assert_eq!(*x.lock().await, coroutine_cnt * loop_cnt);
-// }
+async fn handle(x: Arc<Mutex<i32>>, loop_cnt: i32) {
+    for _ in 0..loop_cnt {
+        let mut guard = x.lock().await;
+        *guard += 1;
+    }
+}
+
+#[test]
+fn mutex_test() {
+    let x = Arc::new(Mutex::new(0));
+    let coroutine_cnt = 10;
+    let loop_cnt = 500;
+    let mut executor = Executor::new();
+    for _ in 0..coroutine_cnt {
+        let x_cloned = x.clone();
+        executor.spawn(handle(x_cloned, loop_cnt));
+    }
+    executor.run();
+    assert_eq!(*block_on(x.lock()), coroutine_cnt * loop_cnt);
+}
+
+#[test]
+fn threads_test() {
+    // Every thread runs its own executor, so wakeups cross threads.
+    let x = Arc::new(Mutex::new(0));
+    let thread_cnt = 4;
+    let loop_cnt = 500;
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let x_cloned = x.clone();
+        threads.push(std::thread::spawn(move || {
+            let mut executor = Executor::new();
+            executor.spawn(handle(x_cloned.clone(), loop_cnt));
+            executor.spawn(handle(x_cloned, loop_cnt));
+            executor.run();
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*x.try_lock().unwrap(), thread_cnt * loop_cnt * 2);
+}
+
+#[test]
+fn fifo_test() {
+    let x = Arc::new(Mutex::new(()));
+    let order = Arc::new(SpinMutex::new(vec![]));
+    let guard = x.try_lock().unwrap();
+    let mut executor = Executor::new();
+    for i in 0..4 {
+        let x = x.clone();
+        let order = order.clone();
+        executor.spawn(async move {
+            let _guard = x.lock().await;
+            order.lock().push(i);
+        });
+    }
+    // Queue everyone up, then let go.
+    assert!(!executor.run_until_stalled());
+    let mut late = Box::pin(x.lock());
+    assert!(poll_once(&mut late).is_pending());
+    drop(guard);
+    executor.run();
+    assert_eq!(*order.lock(), [0, 1, 2, 3]);
+    // The last waiter holds the mutex until it gives up.
+    assert!(x.try_lock().is_none());
+    drop(late);
+    assert!(x.try_lock().is_some());
+}
+
+#[test]
+fn cancel_test() {
+    let x = Mutex::new(0);
+    let guard = x.try_lock().unwrap();
+    let mut first = Box::pin(x.lock());
+    let mut second = Box::pin(x.lock());
+    assert!(poll_once(&mut first).is_pending());
+    assert!(poll_once(&mut second).is_pending());
+    // The mutex is handed to `first`, which gives up and passes it on.
+    drop(guard);
+    drop(first);
+    match poll_once(&mut second) {
+        std::task::Poll::Ready(mut guard) => *guard += 1,
+        std::task::Poll::Pending => panic!("the mutex was not passed on"),
+    }
+    drop(second);
+    assert!(!x.is_locked());
+    assert_eq!(x.into_inner(), 1);
+}
@@ -0,0 +1,111 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use common::executor::{block_on, poll_once, Executor};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::{notify::Notify, spin::SpinMutex};
+
+#[test]
+fn permit_test() {
+    let notify = Notify::new();
+    notify.notify_one();
+    // The permit is taken at once, and there is only one.
+    assert!(poll_once(&mut Box::pin(notify.notified())).is_ready());
+    assert!(poll_once(&mut Box::pin(notify.notified())).is_pending());
+    notify.notify_one();
+    notify.notify_one();
+    block_on(notify.notified());
+    assert!(poll_once(&mut Box::pin(notify.notified())).is_pending());
+}
+
+#[test]
+fn notify_waiters_test() {
+    let notify = Notify::new();
+    let mut polled = Box::pin(notify.notified());
+    let mut unpolled = Box::pin(notify.notified());
+    assert!(poll_once(&mut polled).is_pending());
+    notify.notify_waiters();
+    let mut later = Box::pin(notify.notified());
+    assert!(poll_once(&mut polled).is_ready());
+    assert!(poll_once(&mut unpolled).is_ready());
+    // Nothing is left for futures created after the call.
+    assert!(poll_once(&mut later).is_pending());
+}
+
+#[test]
+fn fifo_test() {
+    let notify = Notify::new();
+    let mut first = Box::pin(notify.notified());
+    let mut second = Box::pin(notify.notified());
+    assert!(poll_once(&mut first).is_pending());
+    assert!(poll_once(&mut second).is_pending());
+    notify.notify_one();
+    assert!(poll_once(&mut second).is_pending());
+    assert!(poll_once(&mut first).is_ready());
+    notify.notify_one();
+    assert!(poll_once(&mut second).is_ready());
+}
+
+#[test]
+fn cancel_test() {
+    let notify = Notify::new();
+    let mut first = Box::pin(notify.notified());
+    let mut second = Box::pin(notify.notified());
+    assert!(poll_once(&mut first).is_pending());
+    assert!(poll_once(&mut second).is_pending());
+    notify.notify_one();
+    // `first` was picked, and passes the notification on as it leaves.
+    drop(first);
+    assert!(poll_once(&mut second).is_ready());
+    // With nobody left to pass it to, it becomes the permit.
+    let mut third = Box::pin(notify.notified());
+    assert!(poll_once(&mut third).is_pending());
+    notify.notify_one();
+    drop(third);
+    assert!(poll_once(&mut Box::pin(notify.notified())).is_ready());
+}
+
+#[test]
+fn cancel_after_notify_waiters_test() {
+    let notify = Notify::new();
+    let mut first = Box::pin(notify.notified());
+    assert!(poll_once(&mut first).is_pending());
+    notify.notify_waiters();
+    // Only a `notify_one` is passed on, so nothing is left behind.
+    drop(first);
+    assert!(poll_once(&mut Box::pin(notify.notified())).is_pending());
+    assert!(format!("{:?}", notify).contains("permit: false"));
+}
+
+#[test]
+fn interrupt_test() {
+    // Another thread plays the interrupt handler filling a queue.
+    let queue = Arc::new(SpinMutex::new(Vec::new()));
+    let notify = Arc::new(Notify::new());
+    let received = Arc::new(AtomicUsize::new(0));
+    let mut executor = Executor::new();
+    {
+        let (queue, notify, received) = (queue.clone(), notify.clone(), received.clone());
+        executor.spawn(async move {
+            let mut sum = 0;
+            while sum < 1000 {
+                let item = queue.lock().pop();
+                match item {
+                    Some(item) => sum += item,
+                    None => notify.notified().await,
+                }
+            }
+            received.store(sum, Ordering::Relaxed);
+        });
+    }
+    let handler = std::thread::spawn(move || {
+        for _ in 0..1000 {
+            queue.lock().push(1);
+            notify.notify_one();
+        }
+    });
+    executor.run();
+    handler.join().unwrap();
+    assert_eq!(received.load(Ordering::Relaxed), 1000);
+}
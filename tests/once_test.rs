@@ -0,0 +1,86 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lock::once::{Lazy, Once, OnceCell};
+
+#[test]
+fn once_race_test() {
+    let once = Arc::new(Once::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut threads = vec![];
+    for i in 0..4 {
+        let once_clone = once.clone();
+        let calls_clone = calls.clone();
+        threads.push(std::thread::spawn(move || {
+            *once_clone.call_once(|| {
+                calls_clone.fetch_add(1, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                i
+            })
+        }));
+    }
+    let values: vec::Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(values.iter().all(|value| *value == values[0]));
+    assert_eq!(once.get(), Some(&values[0]));
+    assert!(once.is_completed());
+}
+
+#[test]
+fn try_call_once_test() {
+    let once = Once::new();
+    assert_eq!(once.try_call_once(|| Err(())), Err(()));
+    assert!(once.get().is_none());
+    assert_eq!(once.try_call_once(|| Ok::<_, ()>(5)), Ok(&5));
+    assert_eq!(*once.wait(), 5);
+    assert_eq!(once.into_inner(), Some(5));
+}
+
+#[test]
+fn once_cell_test() {
+    let cell = OnceCell::new();
+    assert!(cell.get().is_none());
+    assert_eq!(cell.set(1), Ok(()));
+    assert_eq!(cell.set(2), Err(2));
+    assert_eq!(*cell.get_or_init(|| 3), 1);
+    assert_eq!(cell.into_inner(), Some(1));
+}
+
+static LAZY: Lazy<usize> = Lazy::new(|| 42);
+
+#[test]
+fn lazy_test() {
+    let mut threads = vec![];
+    for _ in 0..4 {
+        threads.push(std::thread::spawn(|| *LAZY));
+    }
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), 42);
+    }
+}
+
+#[test]
+#[should_panic(expected = "re-entrant Once initialization")]
+fn reentrant_init_test() {
+    // Stands in for an interrupt handler touching the `Once` its cpu is initializing.
+    let once = Once::new();
+    once.call_once(|| *once.call_once(|| 1) + 1);
+}
+
+#[test]
+fn poison_test() {
+    let cell = Arc::new(OnceCell::<usize>::new());
+    let cell_clone = cell.clone();
+    let result = std::thread::spawn(move || {
+        cell_clone.get_or_init(|| panic!("initializer failed"));
+    })
+    .join();
+    assert!(result.is_err());
+    assert!(cell.is_poisoned());
+    assert!(cell.get().is_none());
+
+    let cell_clone = cell.clone();
+    let result = std::thread::spawn(move || *cell_clone.get_or_init(|| 1)).join();
+    assert!(result.is_err());
+}
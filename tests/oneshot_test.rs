@@ -0,0 +1,49 @@
+mod common;
+
+use common::executor::{block_on, poll_once};
+use lock::oneshot::{self, RecvError, TryRecvError};
+
+#[test]
+fn send_test() {
+    let (tx, mut rx) = oneshot::channel();
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    assert!(poll_once(&mut rx).is_pending());
+    tx.send(42).unwrap();
+    assert_eq!(block_on(rx), Ok(42));
+}
+
+#[test]
+fn dropped_sender_test() {
+    let (tx, mut rx) = oneshot::channel::<i32>();
+    assert!(poll_once(&mut rx).is_pending());
+    drop(tx);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    assert_eq!(block_on(rx), Err(RecvError));
+}
+
+#[test]
+fn closed_test() {
+    let (tx, rx) = oneshot::channel();
+    assert!(!tx.is_closed());
+    drop(rx);
+    assert!(tx.is_closed());
+    assert_eq!(tx.send(1), Err(1));
+
+    // A value sent before `close` can still be received.
+    let (tx, mut rx) = oneshot::channel();
+    tx.send(2).unwrap();
+    rx.close();
+    assert_eq!(rx.try_recv(), Ok(2));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+}
+
+#[test]
+fn interrupt_test() {
+    // Another thread plays the interrupt handler completing each request.
+    for i in 0..100 {
+        let (tx, rx) = oneshot::channel();
+        let handler = std::thread::spawn(move || tx.send(i).unwrap());
+        assert_eq!(block_on(rx), Ok(i));
+        handler.join().unwrap();
+    }
+}
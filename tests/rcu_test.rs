@@ -0,0 +1,134 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lock::rcu::{
+    call_rcu, rcu_barrier, rcu_check_quiescent_state, rcu_cpu_offline, rcu_cpu_online,
+    rcu_quiescent_state, rcu_read_lock, rcu_read_lock_held, rcu_read_unlock, synchronize_rcu,
+    RcuPointer,
+};
+use std::time::Duration;
+
+#[test]
+fn synchronize_waits_for_readers_test() {
+    let reading = Arc::new(AtomicBool::new(false));
+    let release = Arc::new(AtomicBool::new(false));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let reading_clone = reading.clone();
+    let release_clone = release.clone();
+    let done_clone = done.clone();
+    let reader = std::thread::spawn(move || {
+        rcu_cpu_online();
+        let guard = rcu_read_lock();
+        reading_clone.store(true, Ordering::SeqCst);
+        while !release_clone.load(Ordering::SeqCst) {
+            // A tick inside the critical section is not a quiescent state.
+            rcu_check_quiescent_state();
+            std::thread::yield_now();
+        }
+        rcu_read_unlock(guard);
+        while !done_clone.load(Ordering::SeqCst) {
+            rcu_quiescent_state();
+            std::thread::yield_now();
+        }
+        rcu_cpu_offline();
+    });
+    while !reading.load(Ordering::SeqCst) {
+        std::thread::yield_now();
+    }
+
+    let synced = Arc::new(AtomicBool::new(false));
+    let synced_clone = synced.clone();
+    let done_clone = done.clone();
+    let updater = std::thread::spawn(move || {
+        synchronize_rcu();
+        synced_clone.store(true, Ordering::SeqCst);
+        done_clone.store(true, Ordering::SeqCst);
+    });
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!synced.load(Ordering::SeqCst));
+
+    release.store(true, Ordering::SeqCst);
+    updater.join().unwrap();
+    reader.join().unwrap();
+    assert!(synced.load(Ordering::SeqCst));
+}
+
+#[test]
+fn nested_read_test() {
+    rcu_cpu_online();
+    let outer = rcu_read_lock();
+    let inner = rcu_read_lock();
+    rcu_check_quiescent_state();
+    drop(inner);
+    assert!(rcu_read_lock_held());
+    drop(outer);
+    assert!(!rcu_read_lock_held());
+    rcu_quiescent_state();
+    rcu_cpu_offline();
+}
+
+#[test]
+#[should_panic(expected = "quiescent state inside rcu_read_lock")]
+fn quiescent_state_in_reader_test() {
+    // Offline, so that the panic leaves no grace period waiting for us.
+    let _guard = rcu_read_lock();
+    rcu_quiescent_state();
+}
+
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+struct Node(usize);
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        DROPPED.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn rcu_pointer_test() {
+    let x = Arc::new(RcuPointer::new(Node(0)));
+    let done = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..3 {
+        let x_clone = x.clone();
+        let done_clone = done.clone();
+        readers.push(std::thread::spawn(move || {
+            rcu_cpu_online();
+            let mut last = 0;
+            while !done_clone.load(Ordering::SeqCst) {
+                let guard = rcu_read_lock();
+                let value = x_clone.dereference(&guard).unwrap().0;
+                assert!(value >= last);
+                last = value;
+                drop(guard);
+                rcu_quiescent_state();
+            }
+            rcu_cpu_offline();
+        }));
+    }
+    for i in 1..=100 {
+        x.publish(Node(i));
+    }
+    let called = Arc::new(AtomicBool::new(false));
+    let called_clone = called.clone();
+    call_rcu(move || called_clone.store(true, Ordering::SeqCst));
+    rcu_barrier();
+    assert!(called.load(Ordering::SeqCst));
+    // Every replaced node has been freed, only the last one is still published.
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 100);
+
+    done.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let guard = rcu_read_lock();
+    assert_eq!(x.dereference(&guard).unwrap().0, 100);
+    drop(guard);
+    x.clear();
+    rcu_barrier();
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 101);
+    assert!(x.is_null());
+}
@@ -0,0 +1,150 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::{priority_log, set_base_priority, StdScheduler};
+use lock::rt_mutex::{Deadlock, RtMutex};
+use lock::sched::Scheduler;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+type Mutex<T> = RtMutex<T, StdScheduler>;
+
+fn wait_for_log(task: usize, expected: &[usize]) {
+    while priority_log(task) != expected {
+        std::thread::yield_now();
+    }
+}
+
+#[test]
+fn boost_restore_test() {
+    set_base_priority(1);
+    let low = StdScheduler::current_task();
+    let a = Arc::new(Mutex::new(0));
+    let guard = a.lock();
+
+    let a_clone = a.clone();
+    let high = std::thread::spawn(move || {
+        set_base_priority(10);
+        *a_clone.lock() += 1;
+        StdScheduler::current_task()
+    });
+    wait_for_log(low, &[10]);
+    drop(guard);
+    let high = high.join().unwrap();
+    assert_eq!(priority_log(low), [10, 1]);
+    assert!(priority_log(high).is_empty());
+    assert_eq!(*a.lock(), 1);
+}
+
+#[test]
+fn transitive_boost_test() {
+    set_base_priority(1);
+    let low = StdScheduler::current_task();
+    let a = Arc::new(Mutex::new(()));
+    let b = Arc::new(Mutex::new(()));
+    let guard = a.lock();
+
+    let (tx, rx) = channel();
+    let a_clone = a.clone();
+    let b_clone = b.clone();
+    let medium = std::thread::spawn(move || {
+        set_base_priority(5);
+        let b_guard = b_clone.lock();
+        tx.send(StdScheduler::current_task()).unwrap();
+        drop(a_clone.lock());
+        drop(b_guard);
+    });
+    let medium_task = rx.recv().unwrap();
+    wait_for_log(low, &[5]);
+
+    let b_clone = b.clone();
+    let high = std::thread::spawn(move || {
+        set_base_priority(10);
+        drop(b_clone.lock());
+    });
+    // The boost goes through the medium task to us.
+    wait_for_log(low, &[5, 10]);
+    assert_eq!(priority_log(medium_task), [10]);
+
+    drop(guard);
+    medium.join().unwrap();
+    high.join().unwrap();
+    assert_eq!(priority_log(low), [5, 10, 1]);
+    // Still boosted through `b` after getting and releasing `a`.
+    assert_eq!(priority_log(medium_task), [10, 5]);
+}
+
+#[test]
+fn priority_order_test() {
+    set_base_priority(0);
+    let owner = StdScheduler::current_task();
+    let a = Arc::new(Mutex::new(vec::Vec::new()));
+    let guard = a.lock();
+    let mut waiters = vec![];
+    for priority in [2, 8, 5] {
+        let a_clone = a.clone();
+        waiters.push(std::thread::spawn(move || {
+            set_base_priority(priority);
+            a_clone.lock().push(priority);
+        }));
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(priority_log(owner), [2, 8]);
+    drop(guard);
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert_eq!(*a.lock(), [8, 5, 2]);
+    assert_eq!(priority_log(owner), [2, 8, 0]);
+}
+
+#[test]
+fn deadlock_test() {
+    set_base_priority(0);
+    let me = StdScheduler::current_task();
+    let a = Arc::new(Mutex::new(()));
+    let b = Arc::new(Mutex::new(()));
+    let guard = a.lock();
+    assert_eq!(a.lock_checked().err(), Some(Deadlock));
+
+    let (tx, rx) = channel();
+    let a_clone = a.clone();
+    let b_clone = b.clone();
+    let other = std::thread::spawn(move || {
+        set_base_priority(1);
+        let b_guard = b_clone.lock();
+        tx.send(()).unwrap();
+        drop(a_clone.lock());
+        drop(b_guard);
+    });
+    rx.recv().unwrap();
+    wait_for_log(me, &[1]);
+    // The other task holds `b` and waits for our `a`.
+    assert_eq!(b.lock_checked().err(), Some(Deadlock));
+    drop(guard);
+    other.join().unwrap();
+    drop(b.lock());
+}
+
+#[test]
+fn rt_mutex_test() {
+    let x = Arc::new(Mutex::new(0));
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let mut threads = vec![];
+    for i in 0..thread_cnt {
+        let x_clone = x.clone();
+        threads.push(std::thread::spawn(move || {
+            set_base_priority(i);
+            for _ in 0..loop_cnt {
+                *x_clone.lock() += 1;
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*x.lock(), thread_cnt * loop_cnt);
+}
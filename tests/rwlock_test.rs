@@ -0,0 +1,118 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lock::rwlock::{ReaderPreferring, RwLock, RwLockPolicy, WriterPreferring};
+
+fn count_under_read_storm<P: RwLockPolicy + Send + Sync + 'static>(x: Arc<RwLock<usize, P>>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..3 {
+        let x_clone = x.clone();
+        let stop_clone = stop.clone();
+        readers.push(std::thread::spawn(move || {
+            while !stop_clone.load(Ordering::Relaxed) {
+                let guard = x_clone.read();
+                assert!(*guard <= 100);
+            }
+        }));
+    }
+    for _ in 0..100 {
+        *x.write() += 1;
+    }
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(*x.read(), 100);
+}
+
+#[test]
+fn writer_progress_test() {
+    // Only writer preferring promises writers get through a read storm.
+    count_under_read_storm(Arc::new(RwLock::<_, WriterPreferring>::with_policy(0)));
+}
+
+#[test]
+fn pending_writer_test() {
+    let x = Arc::new(RwLock::<_, WriterPreferring>::with_policy(0));
+    let read = x.read();
+    let x_clone = x.clone();
+    let writer = std::thread::spawn(move || *x_clone.write() += 1);
+    // The spinning writer turns new readers away, but not the existing one.
+    while x.try_read().is_some() {
+        std::thread::yield_now();
+    }
+    assert_eq!(x.reader_count(), 1);
+    drop(read);
+    writer.join().unwrap();
+    assert_eq!(*x.read(), 1);
+}
+
+#[test]
+fn upgrade_with_pending_writer_test() {
+    let x = Arc::new(RwLock::<_, WriterPreferring>::with_policy(0));
+    let upgradeable = x.upgradeable_read();
+    let x_clone = x.clone();
+    let writer = std::thread::spawn(move || *x_clone.write() += 1);
+    // Give the writer time to set the pending bit, which must not block the upgrade.
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    *upgradeable.upgrade() += 1;
+    writer.join().unwrap();
+    assert_eq!(*x.read(), 2);
+}
+
+#[test]
+fn reader_preferring_test() {
+    let x = RwLock::<_, ReaderPreferring>::with_policy(0);
+    let upgradeable = x.upgradeable_read();
+    assert!(x.try_read().is_some());
+    drop(upgradeable);
+
+    let x = RwLock::new(0);
+    let upgradeable = x.upgradeable_read();
+    assert!(x.try_read().is_none());
+    drop(upgradeable);
+}
+
+#[test]
+fn read_recursive_test() {
+    let x = Arc::new(RwLock::new(0));
+    let read = x.read();
+    let x_clone = x.clone();
+    let upgrader = std::thread::spawn(move || *x_clone.upgradeable_read().upgrade() += 1);
+    // Wait for the other cpu to hold the upgradeable guard.
+    while x.reader_count() < 2 {
+        std::thread::yield_now();
+    }
+    // A plain read would now deadlock against the upgrader.
+    assert!(x.try_read().is_none());
+    let nested = x.read_recursive();
+    assert_eq!(*nested, 0);
+    drop(nested);
+    drop(read);
+    upgrader.join().unwrap();
+
+    let write = x.write();
+    assert!(x.try_read_recursive().is_none());
+    drop(write);
+    assert_eq!(*x.read_recursive(), 1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "nested RwLock::read")]
+fn nested_read_test() {
+    let x = RwLock::new(0);
+    let _read = x.read();
+    let _nested = x.read();
+}
+
+#[test]
+fn reader_preferring_nested_read_test() {
+    // Only a writer holding the lock refuses readers, so this cannot deadlock.
+    let x = RwLock::<_, ReaderPreferring>::with_policy(0);
+    let read = x.read();
+    let nested = x.read();
+    assert_eq!(*read, *nested);
+}
@@ -0,0 +1,77 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::StdScheduler;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lock::semaphore::Semaphore;
+
+#[test]
+fn try_acquire_test() {
+    let sem = Semaphore::new(3);
+    let a = sem.try_acquire(2).unwrap();
+    assert_eq!(a.num_permits(), 2);
+    assert!(sem.try_acquire(2).is_none());
+    let b = sem.acquire(1);
+    assert_eq!(sem.available_permits(), 0);
+    drop(a);
+    assert_eq!(sem.available_permits(), 2);
+    b.forget();
+    assert_eq!(sem.available_permits(), 2);
+    sem.release(1);
+    assert_eq!(sem.available_permits(), 3);
+}
+
+fn limit_test<S: lock::sched::Scheduler + 'static>(sem: Semaphore<S>) {
+    let sem = Arc::new(sem);
+    let inside = Arc::new(AtomicUsize::new(0));
+    let mut threads = vec![];
+    for i in 0..4 {
+        let sem_clone = sem.clone();
+        let inside_clone = inside.clone();
+        threads.push(std::thread::spawn(move || {
+            for _ in 0..100 {
+                let n = i % 2 + 1;
+                let _permit = sem_clone.acquire(n);
+                let now = inside_clone.fetch_add(n, Ordering::SeqCst) + n;
+                assert!(now <= 3);
+                std::thread::yield_now();
+                inside_clone.fetch_sub(n, Ordering::SeqCst);
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(sem.available_permits(), 3);
+}
+
+#[test]
+fn spin_limit_test() {
+    limit_test(Semaphore::new(3));
+}
+
+#[test]
+fn blocking_limit_test() {
+    limit_test(Semaphore::<StdScheduler>::with_scheduler(3));
+}
+
+#[test]
+fn blocking_wakeup_test() {
+    let sem = Arc::new(Semaphore::<StdScheduler>::with_scheduler(0));
+    let acquired = Arc::new(AtomicBool::new(false));
+    let sem_clone = sem.clone();
+    let acquired_clone = acquired.clone();
+    let waiter = std::thread::spawn(move || {
+        sem_clone.acquire(2).forget();
+        acquired_clone.store(true, Ordering::SeqCst);
+    });
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    sem.release(1);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    assert!(!acquired.load(Ordering::SeqCst));
+    sem.release(1);
+    waiter.join().unwrap();
+    assert_eq!(sem.available_permits(), 0);
+}
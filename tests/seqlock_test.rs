@@ -0,0 +1,92 @@
+extern crate alloc;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lock::seqlock::{SeqCount, SeqLock};
+use lock::spin::SpinMutex;
+
+#[test]
+fn seqlock_test() {
+    let x = Arc::new(SeqLock::new((0usize, 0usize)));
+    let done = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..3 {
+        let x_clone = x.clone();
+        let done_clone = done.clone();
+        readers.push(std::thread::spawn(move || {
+            while !done_clone.load(Ordering::Relaxed) {
+                let (a, b) = x_clone.read();
+                assert_eq!(a, b);
+            }
+        }));
+    }
+    let mut writers = vec![];
+    for _ in 0..2 {
+        let x_clone = x.clone();
+        writers.push(std::thread::spawn(move || {
+            for _ in 0..1000 {
+                let mut guard = x_clone.write();
+                guard.0 += 1;
+                guard.1 += 1;
+            }
+        }));
+    }
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(x.read(), (2000, 2000));
+}
+
+#[test]
+fn try_write_test() {
+    let x = SeqLock::new(1);
+    let guard = x.try_write();
+    assert!(guard.is_some());
+    assert!(x.try_write().is_none());
+    drop(guard);
+    x.set(2);
+    assert_eq!(x.read(), 2);
+}
+
+struct Stats {
+    seq: SeqCount,
+    writer: SpinMutex<()>,
+    values: UnsafeCell<[usize; 4]>,
+}
+
+unsafe impl Sync for Stats {}
+
+#[test]
+fn seqcount_test() {
+    let stats = Arc::new(Stats {
+        seq: SeqCount::new(),
+        writer: SpinMutex::new(()),
+        values: UnsafeCell::new([0; 4]),
+    });
+    let stats_clone = stats.clone();
+    let writer = std::thread::spawn(move || {
+        for i in 1..=1000 {
+            let _guard = stats_clone.writer.lock();
+            stats_clone.seq.write_begin();
+            unsafe { *stats_clone.values.get() = [i; 4] };
+            stats_clone.seq.write_end();
+        }
+    });
+    loop {
+        let seq = stats.seq.read_begin();
+        let values = unsafe { core::ptr::read_volatile(stats.values.get()) };
+        if stats.seq.read_retry(seq) {
+            continue;
+        }
+        assert!(values.iter().all(|v| *v == values[0]));
+        if values[0] == 1000 {
+            break;
+        }
+    }
+    writer.join().unwrap();
+}
@@ -0,0 +1,40 @@
+// On its own, as it holds every simulated cpu for a while.
+use lock::spin::SpinMutex;
+use std::sync::{Arc, Barrier};
+
+const MAX_CORE_NUM: usize = 16;
+
+#[test]
+fn too_many_cpus_test() {
+    let lock = Arc::new(SpinMutex::new(0));
+    let claimed = Arc::new(Barrier::new(MAX_CORE_NUM + 1));
+    let release = Arc::new(Barrier::new(MAX_CORE_NUM + 1));
+    let holders: Vec<_> = (0..MAX_CORE_NUM)
+        .map(|_| {
+            let (lock, claimed, release) = (lock.clone(), claimed.clone(), release.clone());
+            std::thread::spawn(move || {
+                // Locking claims a cpu for the thread.
+                *lock.lock() += 1;
+                claimed.wait();
+                release.wait();
+            })
+        })
+        .collect();
+    claimed.wait();
+    let lock_clone = lock.clone();
+    let err = std::thread::spawn(move || *lock_clone.lock() += 1)
+        .join()
+        .unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.contains("more than MAX_CORE_NUM"), "{}", msg);
+    release.wait();
+    for holder in holders {
+        holder.join().unwrap();
+    }
+    // The cpus are handed back as the threads exit.
+    let lock_clone = lock.clone();
+    std::thread::spawn(move || *lock_clone.lock() += 1)
+        .join()
+        .unwrap();
+    assert_eq!(*lock.lock(), MAX_CORE_NUM + 1);
+}
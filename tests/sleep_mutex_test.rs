@@ -0,0 +1,72 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::StdScheduler;
+use lock::sleep_mutex::SleepMutex;
+use std::time::Duration;
+
+type Mutex<T> = SleepMutex<T, StdScheduler>;
+
+#[test]
+fn sleep_mutex_test() {
+    let x = Arc::new(Mutex::new(0));
+    let thread_cnt = 4;
+    let loop_cnt = 1000;
+    let mut threads = vec![];
+    for _ in 0..thread_cnt {
+        let x_clone = x.clone();
+        threads.push(std::thread::spawn(move || {
+            for _ in 0..loop_cnt {
+                let mut guard = x_clone.lock();
+                *guard += 1;
+            }
+        }));
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(*x.lock(), thread_cnt * loop_cnt);
+}
+
+#[test]
+fn sleeping_holder_test() {
+    // The holder sleeps, so the waiters get past their spinning and block.
+    let x = Arc::new(Mutex::new(vec::Vec::new()));
+    let guard = x.lock();
+    let mut waiters = vec![];
+    for i in 0..3 {
+        let x_clone = x.clone();
+        waiters.push(std::thread::spawn(move || x_clone.lock().push(i)));
+    }
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(x.try_lock().is_none());
+    drop(guard);
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    let mut values = x.lock().clone();
+    values.sort_unstable();
+    assert_eq!(values, [0, 1, 2]);
+}
+
+#[test]
+fn try_lock_test() {
+    let x = Mutex::new(1);
+    let guard = x.try_lock();
+    assert!(guard.is_some());
+    assert!(x.try_lock().is_none());
+    drop(guard);
+    assert!(!x.is_locked());
+    assert_eq!(x.into_inner(), 1);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "SleepMutex locked twice by the same task")]
+fn recursive_lock_test() {
+    let x = Mutex::new(());
+    let _guard = x.lock();
+    let _again = x.lock();
+}
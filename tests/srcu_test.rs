@@ -0,0 +1,101 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::StdScheduler;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lock::sched::{Scheduler, SpinWait};
+use lock::srcu::Srcu;
+use std::time::Duration;
+
+fn synchronize_waits_for_sleeping_reader<S: Scheduler + Send + Sync + 'static>() {
+    let srcu = Arc::new(Srcu::<S>::with_scheduler());
+    let other = Arc::new(Srcu::<S>::with_scheduler());
+    let reading = Arc::new(AtomicBool::new(false));
+    let release = Arc::new(AtomicBool::new(false));
+
+    let srcu_clone = srcu.clone();
+    let reading_clone = reading.clone();
+    let release_clone = release.clone();
+    let reader = std::thread::spawn(move || {
+        let guard = srcu_clone.srcu_read_lock();
+        reading_clone.store(true, Ordering::SeqCst);
+        while !release_clone.load(Ordering::SeqCst) {
+            // Readers may block.
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        srcu_clone.srcu_read_unlock(guard);
+    });
+    while !reading.load(Ordering::SeqCst) {
+        std::thread::yield_now();
+    }
+
+    // Another domain does not wait for this reader.
+    other.synchronize_srcu();
+    assert_eq!(other.completed(), 1);
+
+    let synced = Arc::new(AtomicBool::new(false));
+    let srcu_clone = srcu.clone();
+    let synced_clone = synced.clone();
+    let updater = std::thread::spawn(move || {
+        srcu_clone.synchronize_srcu();
+        synced_clone.store(true, Ordering::SeqCst);
+    });
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!synced.load(Ordering::SeqCst));
+
+    release.store(true, Ordering::SeqCst);
+    updater.join().unwrap();
+    reader.join().unwrap();
+    assert!(synced.load(Ordering::SeqCst));
+    assert_eq!(srcu.completed(), 1);
+}
+
+#[test]
+fn synchronize_waits_for_sleeping_reader_test() {
+    synchronize_waits_for_sleeping_reader::<SpinWait>();
+    // The updater sleeps, and the reader leaving wakes it.
+    synchronize_waits_for_sleeping_reader::<StdScheduler>();
+}
+
+fn srcu_stress<S: Scheduler + Send + Sync + 'static>() {
+    // Readers check that a value they see is not retired before they are done.
+    let srcu = Arc::new(Srcu::<S>::with_scheduler());
+    let current = Arc::new(AtomicUsize::new(0));
+    let retired = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let mut readers = vec![];
+    for _ in 0..4 {
+        let srcu_clone = srcu.clone();
+        let current_clone = current.clone();
+        let retired_clone = retired.clone();
+        let done_clone = done.clone();
+        readers.push(std::thread::spawn(move || {
+            while !done_clone.load(Ordering::SeqCst) {
+                let guard = srcu_clone.srcu_read_lock();
+                let value = current_clone.load(Ordering::SeqCst);
+                std::thread::yield_now();
+                // Everything below `retired` has gone through a grace period.
+                assert!(retired_clone.load(Ordering::SeqCst) <= value);
+                drop(guard);
+            }
+        }));
+    }
+    for i in 1..=100 {
+        current.store(i, Ordering::SeqCst);
+        srcu.synchronize_srcu();
+        retired.store(i, Ordering::SeqCst);
+    }
+    done.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(srcu.completed(), 100);
+}
+
+#[test]
+fn srcu_stress_test() {
+    srcu_stress::<SpinWait>();
+    srcu_stress::<StdScheduler>();
+}
@@ -0,0 +1,126 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use common::StdScheduler;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use lock::wait_queue::WaitQueue;
+use std::time::Duration;
+
+type Queue = WaitQueue<StdScheduler>;
+
+fn wait_for_waiters(queue: &Queue, n: usize) {
+    while queue.len() < n {
+        std::thread::yield_now();
+    }
+}
+
+#[test]
+fn wake_up_all_test() {
+    let queue = Arc::new(Queue::with_scheduler());
+    let ready = Arc::new(AtomicBool::new(false));
+    let mut waiters = vec![];
+    for _ in 0..3 {
+        let queue_clone = queue.clone();
+        let ready_clone = ready.clone();
+        waiters.push(std::thread::spawn(move || {
+            queue_clone.wait_event(|| ready_clone.load(Ordering::SeqCst));
+        }));
+    }
+    wait_for_waiters(&queue, 3);
+    // A wakeup with the condition still false puts them back to sleep.
+    assert_eq!(queue.wake_up_all(), 3);
+    wait_for_waiters(&queue, 3);
+    ready.store(true, Ordering::SeqCst);
+    queue.wake_up_all();
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn exclusive_test() {
+    // Each wake_up_one lets exactly one exclusive waiter take a token.
+    let queue = Arc::new(Queue::with_scheduler());
+    let tokens = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicUsize::new(0));
+    let mut waiters = vec![];
+    for _ in 0..3 {
+        let queue_clone = queue.clone();
+        let tokens_clone = tokens.clone();
+        let done_clone = done.clone();
+        waiters.push(std::thread::spawn(move || {
+            queue_clone.wait_event_exclusive(|| {
+                tokens_clone
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |t| t.checked_sub(1))
+                    .is_ok()
+            });
+            done_clone.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+    wait_for_waiters(&queue, 3);
+    for i in 1..=3 {
+        tokens.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(queue.wake_up_one(), 1);
+        while done.load(Ordering::SeqCst) < i {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(done.load(Ordering::SeqCst), i);
+    }
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+#[test]
+fn non_exclusive_woken_with_exclusive_test() {
+    let queue = Arc::new(Queue::with_scheduler());
+    let ready = Arc::new(AtomicBool::new(false));
+    let mut waiters = vec![];
+    for i in 0..4 {
+        let queue_clone = queue.clone();
+        let ready_clone = ready.clone();
+        waiters.push(std::thread::spawn(move || {
+            let condition = || ready_clone.load(Ordering::SeqCst);
+            if i % 2 == 0 {
+                queue_clone.wait_event(condition);
+            } else {
+                queue_clone.wait_event_exclusive(condition);
+            }
+        }));
+    }
+    wait_for_waiters(&queue, 4);
+    ready.store(true, Ordering::SeqCst);
+    // Both non-exclusive waiters and one of the exclusive ones.
+    assert_eq!(queue.wake_up_one(), 3);
+    queue.wake_up_all();
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+#[test]
+fn timeout_test() {
+    let queue = Queue::with_scheduler();
+    assert!(!queue.wait_event_timeout(|| false, Duration::from_millis(10)));
+    assert!(queue.is_empty());
+    assert!(queue.wait_event_timeout(|| true, Duration::from_millis(10)));
+
+    let queue = Arc::new(queue);
+    let ready = Arc::new(AtomicBool::new(false));
+    let queue_clone = queue.clone();
+    let ready_clone = ready.clone();
+    let waiter = std::thread::spawn(move || {
+        queue_clone.wait_event_timeout(
+            || ready_clone.load(Ordering::SeqCst),
+            Duration::from_secs(10),
+        )
+    });
+    wait_for_waiters(&queue, 1);
+    ready.store(true, Ordering::SeqCst);
+    queue.wake_up_one();
+    assert!(waiter.join().unwrap());
+}
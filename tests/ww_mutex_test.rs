@@ -0,0 +1,189 @@
+extern crate alloc;
+mod common;
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use common::StdScheduler;
+use lock::sched::{Scheduler, SpinWait};
+use lock::ww_mutex::{WwAcquireCtx, WwClass, WwError, WwMutex, WwMutexGuard};
+use std::sync::mpsc::channel;
+
+type Mutex<T> = WwMutex<T, StdScheduler>;
+
+#[test]
+fn basic_test() {
+    let class = WwClass::new();
+    let a = Mutex::new(1);
+    let b = Mutex::new(2);
+    let ctx = WwAcquireCtx::new(&class);
+    let guard_a = a.lock(&ctx).unwrap();
+    let guard_b = b.lock(&ctx).unwrap();
+    assert_eq!(ctx.acquired(), 2);
+    assert_eq!(a.lock(&ctx).err(), Some(WwError::Already));
+    assert!(a.try_lock().is_none());
+    assert_eq!(*guard_a + *guard_b, 3);
+    drop(guard_a);
+    assert_eq!(ctx.acquired(), 1);
+    assert!(a.try_lock().is_some());
+    drop(guard_b);
+    assert_eq!(ctx.acquired(), 0);
+    assert!(WwAcquireCtx::new(&class).stamp() > ctx.stamp());
+}
+
+#[test]
+fn wound_test() {
+    let class = Arc::new(WwClass::new());
+    let a = Arc::new(Mutex::new(0));
+    let b = Arc::new(Mutex::new(0));
+    let old = WwAcquireCtx::new(&class);
+
+    let (tx, rx) = channel();
+    let (b_tx, b_rx) = channel();
+    let (a_clone, b_clone, class_clone) = (a.clone(), b.clone(), class.clone());
+    let young = std::thread::spawn(move || {
+        let ctx = WwAcquireCtx::new(&class_clone);
+        let guard_a = a_clone.lock(&ctx).unwrap();
+        tx.send(()).unwrap();
+        b_rx.recv().unwrap();
+        // Waits for `old` to take `a`, which wounds us.
+        let err = b_clone.lock(&ctx).err();
+        drop(guard_a);
+        let mut guard_b = b_clone.lock_slow(&ctx);
+        let mut guard_a = a_clone.lock(&ctx).unwrap();
+        *guard_a += 1;
+        *guard_b += 1;
+        err
+    });
+    rx.recv().unwrap();
+    let mut guard_b = b.lock(&old).unwrap();
+    b_tx.send(()).unwrap();
+    let mut guard_a = a.lock(&old).unwrap();
+    *guard_a += 1;
+    *guard_b += 1;
+    drop(guard_a);
+    drop(guard_b);
+    assert_eq!(young.join().unwrap(), Some(WwError::Deadlock));
+    assert_eq!(*a.try_lock().unwrap() + *b.try_lock().unwrap(), 4);
+}
+
+// A xorshift generator, to pick lock sets without extra dependencies.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+// Locks every mutex in `set`, backing off and retrying when wounded.
+#[test]
+fn leaked_guard_test() {
+    static CLASS: WwClass = WwClass::new();
+    static M: Mutex<i32> = Mutex::new(0);
+    {
+        let ctx = WwAcquireCtx::new(&CLASS);
+        core::mem::forget(M.lock(&ctx).unwrap());
+        // The context goes away under its leaked guard.
+    }
+    assert!(M.try_lock().is_none());
+    let (tx, rx) = channel();
+    // A newer context still compares against the holder, then waits for good.
+    std::thread::spawn(move || {
+        let ctx = WwAcquireCtx::new(&CLASS);
+        tx.send(()).unwrap();
+        let _guard = M.lock(&ctx);
+        tx.send(()).unwrap();
+    });
+    rx.recv().unwrap();
+    let blocked = rx.recv_timeout(std::time::Duration::from_millis(50));
+    assert!(blocked.is_err());
+}
+
+fn lock_all<'a, S: Scheduler>(
+    ctx: &'a WwAcquireCtx,
+    set: &[&'a WwMutex<usize, S>],
+) -> Vec<WwMutexGuard<'a, usize, S>> {
+    let mut guards = Vec::new();
+    let mut contended: Option<usize> = None;
+    loop {
+        if let Some(index) = contended.take() {
+            guards.push(set[index].lock_slow(ctx));
+        }
+        let mut backed_off = false;
+        for (index, mutex) in set.iter().enumerate() {
+            match mutex.lock(ctx) {
+                Ok(guard) => guards.push(guard),
+                Err(WwError::Already) => {}
+                Err(WwError::Deadlock) => {
+                    guards.clear();
+                    contended = Some(index);
+                    backed_off = true;
+                    break;
+                }
+            }
+        }
+        if !backed_off {
+            ctx.done();
+            return guards;
+        }
+    }
+}
+
+fn stress_test<S: Scheduler + 'static>(thread_cnt: usize, loop_cnt: usize) {
+    let lock_cnt = 8;
+    let class = Arc::new(WwClass::new());
+    let locks: Arc<Vec<WwMutex<usize, S>>> =
+        Arc::new((0..lock_cnt).map(|_| WwMutex::new(0)).collect());
+    let mut threads = vec![];
+    for i in 0..thread_cnt {
+        let class = class.clone();
+        let locks = locks.clone();
+        threads.push(std::thread::spawn(move || {
+            let mut rng = Rng(0x9e37_79b9_7f4a_7c15 ^ (i as u64 + 1));
+            let mut expected = vec![0; lock_cnt];
+            for _ in 0..loop_cnt {
+                // A random set in a random order, possibly naming a lock twice.
+                let indices: Vec<usize> =
+                    (0..2 + rng.next(3)).map(|_| rng.next(lock_cnt)).collect();
+                let set: Vec<&WwMutex<usize, S>> = indices.iter().map(|i| &locks[*i]).collect();
+                let ctx = WwAcquireCtx::new(&class);
+                let mut guards = lock_all(&ctx, &set);
+                assert_eq!(ctx.acquired(), guards.len());
+                for guard in guards.iter_mut() {
+                    **guard += 1;
+                }
+                let mut unique = indices;
+                unique.sort_unstable();
+                unique.dedup();
+                assert_eq!(unique.len(), guards.len());
+                for index in unique {
+                    expected[index] += 1;
+                }
+            }
+            expected
+        }));
+    }
+    let mut expected = vec![0; lock_cnt];
+    for thread in threads {
+        for (total, count) in expected.iter_mut().zip(thread.join().unwrap()) {
+            *total += count;
+        }
+    }
+    for (lock, count) in locks.iter().zip(expected) {
+        assert_eq!(*lock.try_lock().unwrap(), count);
+    }
+}
+
+#[test]
+fn stress_sleep_test() {
+    stress_test::<StdScheduler>(4, 300);
+}
+
+#[test]
+fn stress_spin_test() {
+    stress_test::<SpinWait>(4, 100);
+}